@@ -1,8 +1,14 @@
 load("//bazel/crates:syn.bzl", "syn")
 load("//bazel/crates:uuid.bzl", "uuid")
 load("//bazel/crates:quote.bzl", "quote")
+load("//bazel/crates:linkme.bzl", "linkme")
+load("//bazel/crates:serde.bzl", "serde")
+load("//bazel/crates:erased_serde.bzl", "erased_serde")
 
 def dynamic_dependencies():
       quote()
       syn()
       uuid()
+      linkme()
+      serde()
+      erased_serde()
@@ -0,0 +1,213 @@
+//! The `builder` option of #[subclass]
+//!
+//! Setters are inherent methods of the `<Name>Builder` struct generated for each class, so they need no import.
+//! A class knows only its own fields: the setters of its parents are collected by calling the macro
+//! each class with the option declares under its own name, which expands to `__builder!` with the setters
+//! of the class and of its parents appended, as `name depth index` where 'depth' counts the `Class::Parent`
+//! steps from the class to the one declaring the field.
+use syn::{*, __private::TokenStream2, parse::{Parse, ParseStream}, punctuated::Punctuated};
+use quote::{quote, format_ident};
+
+use crate::Options;
+
+/// Items of a class with the `builder` option, declared next to the struct
+pub fn expand(item: &ItemStruct, parent: &Type, parentField: Option<&Ident>, options: &Options) -> TokenStream2 {
+      if !options.has("builder") {
+            return quote! {};
+      }
+      let name = &item.ident;
+      let vis = &item.vis;
+      let fields = match item.fields {
+            Fields::Named(ref fields) => fields.named.iter().collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => return Error::new_spanned(&item.ident, "the `builder` option requires named fields").to_compile_error(),
+      };
+      let names: Vec<_> = fields.iter().filter_map(|field| field.ident.as_ref()).collect();
+      let values = names.iter().map(|name| match parentField {
+            Some(parentField) if parentField == *name => quote! { <#parent as dynamic_object::builder::Build>::build_default() },
+            _ => quote! { Default::default() },
+      });
+      let default = match item.fields {
+            Fields::Unit => quote! { Self },
+            _ => quote! { Self { #(#names: #values),* } },
+      };
+
+      let setters: Vec<_> = fields.iter().filter(|field| field.ident.as_ref() != parentField).collect();
+      let setterNames: Vec<_> = setters.iter().map(|field| &field.ident).collect();
+      let types = setters.iter().map(|field| &field.ty);
+      let indices = 0..setters.len();
+      let members = setterNames.clone();
+      let payload = quote! { #vis #name { #default } [#(#setterNames)*] };
+      let setters = match parentField {
+            Some(_) => match parent {
+                  Type::Path(parent) if parent.qself.is_none() => {
+                        let parent = &parent.path;
+                        quote! { #parent! { #payload } }
+                  },
+                  _ => Error::new_spanned(parent, "the `builder` option requires the parent to be a path").to_compile_error(),
+            },
+            None => quote! { dynamic_object::__builder! { #payload ; } },
+      };
+      quote! {
+            #(
+                  impl dynamic_object::builder::Field<#indices> for #name {
+                        type Type = #types;
+
+                        fn set(&mut self, value: #types) {
+                              self.#members = value;
+                        }
+                  }
+            )*
+
+            #setters
+      }
+}
+
+/// Setter of a builder: its name, the number of `Class::Parent` steps to the class declaring it and its index there
+struct Setter {
+      name: Ident,
+      depth: usize,
+      index: usize,
+}
+
+impl Parse for Setter {
+      fn parse(input: ParseStream) -> Result<Self> {
+            Ok(Self {
+                  name: input.parse()?,
+                  depth: input.parse::<LitInt>()?.base10_parse()?,
+                  index: input.parse::<LitInt>()?.base10_parse()?,
+            })
+      }
+}
+
+/// Input of `__builder!`: the class, the body of its `build_default`, its own setters and those of its parents
+pub struct Setters {
+      vis: Visibility,
+      name: Ident,
+      default: TokenStream2,
+      own: Vec<Ident>,
+      inherited: Vec<Setter>,
+}
+
+impl Parse for Setters {
+      fn parse(input: ParseStream) -> Result<Self> {
+            let vis = input.parse()?;
+            let name = input.parse()?;
+            let default;
+            braced!(default in input);
+            let own;
+            bracketed!(own in input);
+            let mut names = Vec::new();
+            while !own.is_empty() {
+                  names.push(own.parse()?);
+            }
+            input.parse::<Token![;]>()?;
+            let inherited = Punctuated::<Setter, Token![,]>::parse_terminated(input)?;
+            Ok(Self {
+                  vis,
+                  name,
+                  default: default.parse()?,
+                  own: names,
+                  inherited: inherited.into_iter().collect(),
+            })
+      }
+}
+
+/// The builder struct of a class, its setters and the macro giving them to its subclasses
+pub fn setters(input: Setters) -> TokenStream2 {
+      let Setters { vis, name, default, own, inherited } = input;
+      for setter in &own {
+            if setter == "build" || setter == "build_object" {
+                  let message = format!("the builder setter `{}` of {} has the name of a method of the builder", setter, name);
+                  return Error::new_spanned(setter, message).to_compile_error();
+            }
+            if inherited.iter().any(|parent| parent.name == *setter) {
+                  let message = format!("the builder setter `{}` of {} has the name of a setter of a parent class", setter, name);
+                  return Error::new_spanned(setter, message).to_compile_error();
+            }
+      }
+
+      let setters: Vec<_> = own.iter().enumerate()
+            .map(|(index, name)| Setter { name: name.clone(), depth: 0, index })
+            .chain(inherited.into_iter().map(|setter| Setter { depth: setter.depth + 1, ..setter }))
+            .collect();
+      let names: Vec<_> = setters.iter().map(|setter| &setter.name).collect();
+      let depths: Vec<_> = setters.iter().map(|setter| setter.depth).collect();
+      let indices: Vec<_> = setters.iter().map(|setter| setter.index).collect();
+      let classes = setters.iter().map(|setter| {
+            (0..setter.depth).fold(quote! { #name }, |class, _| quote! { <#class as dynamic_object::Class>::Parent })
+      });
+
+      let builder = format_ident!("{}Builder", name);
+      let callback = format_ident!("__dynamic_object_builder_{}", uuid::Uuid::new_v4().simple().to_string());
+      // Macros can not be exported from the crate by a `use`
+      let callbackVis = match vis {
+            Visibility::Public(_) => quote! { pub(crate) },
+            ref vis => quote! { #vis },
+      };
+      let doc = format!("Builder of [`{}`] setting the fields of every level of the class, returned by `{}::builder()`", name, name);
+      quote! {
+            #[doc = #doc]
+            #vis struct #builder(dynamic_object::builder::Builder<#name>);
+
+            impl dynamic_object::builder::Build for #name {
+                  const SETTERS: &'static [&'static str] = &[#(stringify!(#names)),*];
+
+                  fn build_default() -> Self {
+                        #default
+                  }
+            }
+
+            impl #name {
+                  /// A builder setting the fields of every level of the class
+                  #[allow(dead_code)]
+                  #vis fn builder() -> #builder {
+                        #builder(dynamic_object::builder::Builder::new())
+                  }
+            }
+
+            #[allow(dead_code)]
+            impl #builder {
+                  #(
+                        #vis fn #names(mut self, value: <#classes as dynamic_object::builder::Field<#indices>>::Type) -> Self {
+                              dynamic_object::builder::Field::<#indices>::set(self.0.part_mut::<#classes>(), value);
+                              self
+                        }
+                  )*
+
+                  #vis fn build(self) -> #name {
+                        self.0.build()
+                  }
+
+                  /// Box the value, checking its invariants in debug builds
+                  #vis fn build_object(self) -> dynamic_object::Object<#name> {
+                        self.0.build_object()
+                  }
+            }
+
+            impl core::ops::Deref for #builder {
+                  type Target = dynamic_object::builder::Builder<#name>;
+
+                  fn deref(&self) -> &Self::Target {
+                        &self.0
+                  }
+            }
+
+            impl core::ops::DerefMut for #builder {
+                  fn deref_mut(&mut self) -> &mut Self::Target {
+                        &mut self.0
+                  }
+            }
+
+            #[doc(hidden)]
+            macro_rules! #callback {
+                  ($($class:tt)*) => {
+                        dynamic_object::__builder! { $($class)* ; #(#names #depths #indices),* }
+                  };
+            }
+
+            #[doc(hidden)]
+            #[allow(unused_imports)]
+            #callbackVis use #callback as #name;
+      }
+}
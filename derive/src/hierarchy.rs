@@ -0,0 +1,146 @@
+//! Collects the #[subclass] tree declared inside an inline module
+use syn::*;
+use syn::parse::ParseStream;
+
+use crate::SubclassArgs;
+
+pub struct Node {
+      pub name: Ident,
+      pub vis: Visibility,
+      pub parent: Option<usize>,
+      /// Field holding the parent, None for the root
+      pub parentField: Option<Ident>,
+      pub depth: usize,
+}
+
+pub struct Hierarchy {
+      pub nodes: Vec<Node>,
+      pub root: usize,
+}
+
+impl Hierarchy {
+      /// Every class in the subtree of 'node' (itself included), deepest first
+      pub fn subtree(&self, node: usize) -> Vec<usize> {
+            let mut result: Vec<usize> = (0..self.nodes.len())
+                  .filter(|&i| self.isDescendant(i, node))
+                  .collect();
+            result.sort_by(|a, b| self.nodes[*b].depth.cmp(&self.nodes[*a].depth));
+            result
+      }
+
+      fn isDescendant(&self, mut node: usize, ancestor: usize) -> bool {
+            loop {
+                  if node == ancestor {
+                        return true;
+                  }
+                  match self.nodes[node].parent {
+                        Some(parent) => node = parent,
+                        None => return false,
+                  }
+            }
+      }
+}
+
+/// Parent class and parent field of a #[subclass(ParentT, parent_field, ..)] attribute
+fn parentOf(item: &ItemStruct, attr: &Attribute) -> Option<(Ident, Option<Ident>)> {
+      let segment = attr.path.segments.last()?;
+      if segment.ident != "subclass" {
+            return None;
+      }
+      let (parent, field) = attr.parse_args_with(|input: ParseStream| {
+            let parent: Type = input.parse()?;
+            let mut field = None;
+            if input.parse::<Option<Token![,]>>()?.is_some() && input.peek(Ident) {
+                  field = Some(input.parse::<Ident>()?);
+            }
+            input.parse::<__private::TokenStream2>()?;
+            Ok((parent, field))
+      }).ok()?;
+      let field = field.filter(|field| {
+            item.fields.iter().any(|f| f.ident.as_ref() == Some(field))
+      });
+      match parent {
+            Type::Path(path) => Some((path.path.segments.last()?.ident.clone(), field)),
+            _ => None,
+      }
+}
+
+/// Reads every #[subclass] struct of an inline module and links them to their parents
+///
+/// The root is the only class whose parent is declared outside of the module
+pub fn collect(module: &ItemMod) -> Result<Hierarchy> {
+      let items = match module.content {
+            Some((_, ref items)) => items,
+            None => return Err(Error::new_spanned(module, "expected an inline module containing the class hierarchy")),
+      };
+
+      let mut classes = Vec::new();
+      for item in items {
+            if let Item::Struct(item) = item {
+                  if let Some((parent, field)) = item.attrs.iter().find_map(|attr| parentOf(item, attr)) {
+                        classes.push((item, parent, field));
+                  }
+            }
+      }
+
+      let index = |name: &Ident| classes.iter().position(|(class, _, _)| class.ident == *name);
+      let mut nodes: Vec<Node> = classes.iter()
+            .map(|(item, parent, field)| Node {
+                  name: item.ident.clone(),
+                  vis: item.vis.clone(),
+                  parent: index(parent),
+                  parentField: field.clone(),
+                  depth: 0,
+            })
+            .collect();
+
+      let roots: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].parent.is_none()).collect();
+      let root = match roots.as_slice() {
+            [root] => *root,
+            [] => return Err(Error::new_spanned(&module.ident, "no root class found in the hierarchy")),
+            _ => return Err(Error::new_spanned(&module.ident, "a hierarchy must have exactly one root class")),
+      };
+
+      for i in 0..nodes.len() {
+            let mut depth = 0;
+            let mut node = i;
+            while let Some(parent) = nodes[node].parent {
+                  depth += 1;
+                  node = parent;
+                  if depth > nodes.len() {
+                        return Err(Error::new_spanned(&nodes[i].name, "cyclic class hierarchy"));
+                  }
+            }
+            nodes[i].depth = depth;
+      }
+
+      Ok(Hierarchy {
+            nodes,
+            root,
+      })
+}
+
+/// Adds the `sealed` option to the #[subclass] attributes of the module
+pub fn seal(module: &mut ItemMod) {
+      let items = match module.content {
+            Some((_, ref mut items)) => items,
+            None => return,
+      };
+      for item in items {
+            if let Item::Struct(item) = item {
+                  for attr in &mut item.attrs {
+                        if attr.path.segments.last().is_none_or(|segment| segment.ident != "subclass") {
+                              continue;
+                        }
+                        // Left as is, #[subclass] reports the error
+                        let SubclassArgs { parent, mut idents, options } = match attr.parse_args() {
+                              Ok(args) => args,
+                              Err(_) => continue,
+                        };
+                        idents.push(Ident::new("sealed", __private::Span::call_site()));
+                        let (names, values): (Vec<_>, Vec<_>) = options.into_iter().unzip();
+                        attr.tokens = quote::quote! { (#parent #(, #idents)* #(, #names = #values)*) };
+                  }
+            }
+      }
+}
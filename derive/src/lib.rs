@@ -2,51 +2,101 @@
 #![allow(non_snake_case)]
 
 use proc_macro::{TokenStream};
-use syn::{*, parse::Parse, punctuated::Punctuated};
+use syn::{*, parse::Parse};
 use std::time::UNIX_EPOCH;
-use quote::quote;
+use quote::{quote, format_ident};
 use uuid;
 
 extern crate proc_macro;
 
-struct Parent {
+mod builder;
+mod hierarchy;
+mod match_class;
+mod method;
+mod visit;
+
+/// Arguments of #[subclass(ParentT, parent_field, option, option = value, ..)]
+struct SubclassArgs {
       parent: Type,
+      /// Identifiers following the parent, the first one may be the parent field
+      idents: Vec<Ident>,
+      options: Vec<(Ident, Lit)>,
 }
 
-impl Parse for Parent {
-      fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-            let vars = Punctuated::<Type, Token![,]>::parse_terminated(input)?;
-            let parent = match vars.first() {
-                  Some(p) => p,
-                  None => {
-                        panic!("Error: #[subclass] takes a parent(ParentT, parent_field)");
-                  },
-            };
-
+impl Parse for SubclassArgs {
+      fn parse(input: parse::ParseStream) -> Result<Self> {
+            if input.is_empty() {
+                  return Err(input.error("#[subclass] takes a parent(ParentT, parent_field)"));
+            }
+            let parent = input.parse()?;
+            let mut idents = Vec::new();
+            let mut options = Vec::new();
+            while !input.is_empty() {
+                  input.parse::<Token![,]>()?;
+                  if input.is_empty() {
+                        break;
+                  }
+                  let ident: Ident = input.parse()?;
+                  if input.parse::<Option<Token![=]>>()?.is_some() {
+                        options.push((ident, input.parse()?));
+                  } else {
+                        idents.push(ident);
+                  }
+            }
             Ok(Self {
-                  parent: parent.clone(),
+                  parent,
+                  idents,
+                  options,
             })
       }
 }
 
-struct ParentFieldName {
-      name: Option<Ident>
+/// Options of #[subclass] after the parent field
+struct Options {
+      flags: Vec<Ident>,
+      values: Vec<(Ident, Lit)>,
 }
 
-impl Parse for ParentFieldName {
-      fn parse(input: parse::ParseStream) -> Result<Self> {
-            let vars = Punctuated::<Ident, Token![,]>::parse_terminated(input)?;
-            let vars: Vec<_> = vars.iter().collect();
+impl Options {
+      const FLAGS: &'static [&'static str] = &["any", "serde", "binary", "clone", "eq", "hash", "ord", "lifecycle", "deref", "builder", "sealed"];
+      const VALUES: &'static [&'static str] = &["serde_name", "version"];
 
-            let name = if vars.len() > 1 {
-                  Some(vars[1].clone())
-            } else {
-                  None
-            };
+      fn new(flags: Vec<Ident>, values: Vec<(Ident, Lit)>) -> Result<Self> {
+            for flag in &flags {
+                  if !Self::FLAGS.iter().any(|name| flag == name) {
+                        return Err(Error::new_spanned(flag, format!("unknown #[subclass] option `{}`", flag)));
+                  }
+            }
+            for (name, _) in &values {
+                  if !Self::VALUES.iter().any(|value| name == value) {
+                        return Err(Error::new_spanned(name, format!("unknown #[subclass] option `{}`", name)));
+                  }
+            }
             Ok(Self {
-                  name
+                  flags,
+                  values,
             })
       }
+
+      fn has(&self, name: &str) -> bool {
+            self.flags.iter().any(|flag| flag == name)
+      }
+
+      fn value(&self, name: &str) -> Option<&Lit> {
+            self.values.iter().find(|(option, _)| option == name).map(|(_, value)| value)
+      }
+}
+
+impl SubclassArgs {
+      /// Splits the identifiers into the parent field, if the struct has a field of that name, and the options
+      fn resolve(self, item: &ItemStruct) -> Result<(Type, Option<Ident>, Options)> {
+            let mut idents = self.idents.into_iter().peekable();
+            let parentField = idents.next_if(|ident| {
+                  item.fields.iter().any(|field| field.ident.as_ref() == Some(ident))
+            });
+            let options = Options::new(idents.collect(), self.options)?;
+            Ok((self.parent, parentField, options))
+      }
 }
 
 fn generateID(name: &Ident) -> String {
@@ -55,16 +105,12 @@ fn generateID(name: &Ident) -> String {
       format!("{:?}{}{}", now, id, name.to_string())
 }
 
-fn offsetof(parent: &Type, parentField: ParentFieldName) -> __private::TokenStream2 {
-      match parentField.name {
+fn offsetof(parent: &Type, parentField: Option<&Ident>) -> __private::TokenStream2 {
+      match parentField {
             Some(name) => {
                   return quote! {
                         fn offset() -> isize {
-                              let this: *const Self = 0 as *const Self;
-                              let parent = unsafe {
-                                    &((*this).#name) as *const _ as *const u8
-                              };
-                              parent as isize
+                              core::mem::offset_of!(Self, #name) as isize
                         }
                   }
             },
@@ -92,39 +138,718 @@ fn offsetof(parent: &Type, parentField: ParentFieldName) -> __private::TokenStre
       panic!("Expect parent field name in second argument of attribute");
 }
 
+/// Removes the helper attributes called 'name' (#[name] or #[path::name]) from 'attrs'
+fn takeAttributes(attrs: &mut Vec<Attribute>, name: &str) -> Vec<Attribute> {
+      let (taken, kept) = attrs.drain(..).partition(|attr| {
+            attr.path.segments.last().is_some_and(|segment| segment.ident == name)
+      });
+      *attrs = kept;
+      taken
+}
+
+/// Moves a helper attribute written above #[subclass] below it, where #[subclass] reads it
+fn helperAttribute(name: &str, args: TokenStream, tokens: TokenStream) -> TokenStream {
+      let mut item = parse_macro_input!(tokens as ItemStruct);
+      let args = __private::TokenStream2::from(args);
+      let ident = Ident::new(name, __private::Span::call_site());
+      let attr: Attribute = if args.is_empty() {
+            parse_quote!(#[dynamic_object::#ident])
+      } else {
+            parse_quote!(#[dynamic_object::#ident(#args)])
+      };
+      match item.attrs.iter().position(|attr| attr.path.segments.last().is_some_and(|segment| segment.ident == "subclass")) {
+            Some(index) => {
+                  // After the helpers of the same name already moved, keeping the order they were written in
+                  let isHelper = |attr: &Attribute| attr.path.segments.last().is_some_and(|segment| segment.ident == name);
+                  let after = item.attrs[index + 1..].iter().take_while(|attr| isHelper(attr)).count();
+                  item.attrs.insert(index + 1 + after, attr)
+            },
+            None => {
+                  let message = format!("#[{}] can only be used on a #[subclass] struct", name);
+                  return TokenStream::from(Error::new_spanned(&item.ident, message).to_compile_error());
+            }
+      }
+      TokenStream::from(quote! { #item })
+}
+
+/// ClassInfo::construct of a #[constructible] or #[constructible(constructor)] class
+fn constructor(name: &Ident, attrs: &[Attribute]) -> (__private::TokenStream2, __private::TokenStream2) {
+      let attr = match attrs.first() {
+            Some(attr) => attr,
+            None => return (quote! {}, quote! {}),
+      };
+      let value = if attr.tokens.is_empty() {
+            quote! {
+                  let _ = args;
+                  <Self as Default>::default()
+            }
+      } else {
+            let constructor: Path = match attr.parse_args() {
+                  Ok(constructor) => constructor,
+                  Err(err) => return (quote! {}, err.to_compile_error()),
+            };
+            quote! { #constructor(args.downcast_ref()?) }
+      };
+
+      let field = quote! {
+            construct: Some(#name::__dynamic_object_construct),
+      };
+      let items = quote! {
+            impl #name {
+                  #[doc(hidden)]
+                  fn __dynamic_object_construct(args: &dyn core::any::Any) -> Option<dynamic_object::Object<dynamic_object::DynamicObjectBase>> {
+                        let object: Self = { #value };
+                        Some(dynamic_object::__private::boxed::<Self, _>(object))
+                  }
+            }
+      };
+      (field, items)
+}
+
+/// ClassInfo::invariants of a struct with #[invariant(path)] attributes, 'path' taking &Self
+fn invariants(name: &Ident, attrs: &[Attribute]) -> Result<__private::TokenStream2> {
+      if attrs.is_empty() {
+            return Ok(quote! {});
+      }
+      let paths = attrs.iter().map(|attr| attr.parse_args::<Path>()).collect::<Result<Vec<_>>>()?;
+      let names = paths.iter().map(|path| quote!(#path).to_string().replace(' ', ""));
+      Ok(quote! {
+            invariants: &[#(
+                  dynamic_object::invariant::Invariant {
+                        name: #names,
+                        check: |object| #paths(unsafe { &*(object as *const #name) }),
+                  }
+            ),*],
+      })
+}
+
+/// ClassInfo::fields of a struct, 'parentField' being the field holding the parent
+/// The accessors through `&dyn Any`, which require 'static field types, only with the `any` option
+fn fields(item: &ItemStruct, parent: &Type, parentField: Option<&Ident>, options: &Options) -> __private::TokenStream2 {
+      let fields = item.fields.iter().enumerate().map(|(i, field)| {
+            let name = &item.ident;
+            let ty = &field.ty;
+            let member = match field.ident {
+                  Some(ref ident) => Member::Named(ident.clone()),
+                  None => Member::Unnamed(Index::from(i)),
+            };
+            let fieldName = match field.ident {
+                  Some(ref ident) => ident.to_string(),
+                  None => i.to_string(),
+            };
+            let parentInfo = match (field.ident.as_ref(), parentField) {
+                  (Some(ident), Some(parentField)) if ident == parentField => {
+                        quote! { Some(<#parent as dynamic_object::Class>::info) }
+                  },
+                  _ => quote! { None },
+            };
+            let accessors = if options.has("any") {
+                  quote! {
+                        get: |object| Some(&object.downcast_ref::<#name>()?.#member),
+                        get_mut: |object| Some(&mut object.downcast_mut::<#name>()?.#member),
+                        set: |object, value| match object.downcast_mut::<#name>() {
+                              Some(object) => {
+                                    object.#member = *value.downcast::<#ty>()?;
+                                    Ok(())
+                              },
+                              None => Err(value),
+                        },
+                  }
+            } else {
+                  quote! {
+                        get: |_| None,
+                        get_mut: |_| None,
+                        set: |_, value| Err(value),
+                  }
+            };
+            quote! {
+                  dynamic_object::reflect::FieldInfo {
+                        name: #fieldName,
+                        type_name: core::any::type_name::<#ty>,
+                        offset: core::mem::offset_of!(#name, #member),
+                        size: core::mem::size_of::<#ty>(),
+                        debug: |field, f| {
+                              use dynamic_object::format::{NoDebug as _, ProbeDebug as _};
+                              unsafe { (&dynamic_object::format::Probe::<#ty>::new()).debug(field, f) }
+                        },
+                        to_value: |field| {
+                              use dynamic_object::value::{NoValue as _, ProbeValue as _};
+                              unsafe { (&dynamic_object::format::Probe::<#ty>::new()).to_value(field) }
+                        },
+                        set_value: |field, value| {
+                              use dynamic_object::value::{NoValue as _, ProbeValue as _};
+                              unsafe { (&dynamic_object::format::Probe::<#ty>::new()).set_value(field, value) }
+                        },
+                        accepts_value: |value| {
+                              use dynamic_object::value::{NoValue as _, ProbeValue as _};
+                              (&dynamic_object::format::Probe::<#ty>::new()).accepts_value(value)
+                        },
+                        equals: |a, b| {
+                              use dynamic_object::compare::{NoEq as _, ProbeEq as _};
+                              unsafe { (&dynamic_object::format::Probe::<#ty>::new()).equals(a, b) }
+                        },
+                        parent: #parentInfo,
+                        #accessors
+                  }
+            }
+      });
+      quote! {
+            &[#(#fields),*]
+      }
+}
+
+/// Encode and Decode of a class with the `binary` option, and the ClassInfo fields using them
+fn binary(item: &ItemStruct, options: &Options) -> (__private::TokenStream2, __private::TokenStream2) {
+      if !options.has("binary") {
+            return (quote! {}, quote! {});
+      }
+      let name = &item.ident;
+      let members: Vec<_> = item.fields.iter().enumerate().map(|(i, field)| match field.ident {
+            Some(ref ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+      }).collect();
+
+      let types = item.fields.iter().map(|field| &field.ty);
+      let names = item.fields.iter().enumerate().map(|(i, field)| match field.ident {
+            Some(ref ident) => ident.to_string(),
+            None => i.to_string(),
+      });
+
+      let fields = quote! {
+            schema: <#name as dynamic_object::binary::Decode>::SCHEMA,
+            encode: Some(|object| object as *const #name as *const dyn dynamic_object::binary::Encode),
+            decode: Some(|reader| {
+                  let object = <#name as dynamic_object::binary::Decode>::decode(reader)?;
+                  Ok(dynamic_object::__private::boxed::<#name, _>(object))
+            }),
+      };
+      let items = quote! {
+            impl dynamic_object::binary::Encode for #name {
+                  fn encode(&self, writer: &mut dynamic_object::binary::Writer) -> Result<(), dynamic_object::binary::Error> {
+                        #(dynamic_object::binary::Encode::encode(&self.#members, writer)?;)*
+                        Ok(())
+                  }
+            }
+
+            impl dynamic_object::binary::Decode for #name {
+                  const SCHEMA: u64 = {
+                        let hash = dynamic_object::binary::schema(stringify!(#name), &[]);
+                        #(
+                              let hash = dynamic_object::layout::hash_str(hash, #names);
+                              let hash = dynamic_object::layout::hash_u64(hash, <#types as dynamic_object::binary::Decode>::SCHEMA);
+                        )*
+                        hash
+                  };
+
+                  fn decode(reader: &mut dynamic_object::binary::Reader) -> Result<Self, dynamic_object::binary::Error> {
+                        let _ = &reader;
+                        Ok(Self {
+                              #(#members: dynamic_object::binary::Decode::decode(reader)?,)*
+                        })
+                  }
+            }
+      };
+      (fields, items)
+}
+
+/// Class::LAYOUT_HASH, from the parent's fingerprint and the name, type, size, alignment and offset of each field
+fn layoutHash(item: &ItemStruct, parent: &Type) -> __private::TokenStream2 {
+      let name = &item.ident;
+      let fields = item.fields.iter().enumerate().map(|(i, field)| {
+            let ty = &field.ty;
+            let (member, fieldName) = match field.ident {
+                  Some(ref ident) => (Member::Named(ident.clone()), ident.to_string()),
+                  None => (Member::Unnamed(Index::from(i)), i.to_string()),
+            };
+            quote! {
+                  let hash = dynamic_object::layout::hash_str(hash, #fieldName);
+                  let hash = dynamic_object::layout::hash_str(hash, stringify!(#ty));
+                  let hash = dynamic_object::layout::hash_usize(hash, core::mem::size_of::<#ty>());
+                  let hash = dynamic_object::layout::hash_usize(hash, core::mem::align_of::<#ty>());
+                  let hash = dynamic_object::layout::hash_usize(hash, core::mem::offset_of!(#name, #member));
+            }
+      });
+      quote! {
+            {
+                  let hash = <#parent as dynamic_object::Class>::LAYOUT_HASH;
+                  let hash = dynamic_object::layout::hash_usize(hash, core::mem::size_of::<#name>());
+                  let hash = dynamic_object::layout::hash_usize(hash, core::mem::align_of::<#name>());
+                  #(#fields)*
+                  hash
+            }
+      }
+}
+
+/// ClassInfo fields calling the std traits of the class: Debug and Display if implemented, and those of the `clone`, `lifecycle`, `eq`, `hash` and `ord` options,
+/// with the marker traits of the comparison options
+fn functions(name: &Ident, options: &Options) -> (__private::TokenStream2, __private::TokenStream2) {
+      let object = quote! { unsafe { &*(object as *const #name) } };
+      let other = quote! { unsafe { &*(other as *const #name) } };
+      let mut functions = quote! {
+            debug: |object, f| {
+                  use dynamic_object::format::{NoDebug as _, ProbeDebug as _};
+                  unsafe { (&dynamic_object::format::Probe::<#name>::new()).debug(object, f) }
+            },
+            display: |object, f| {
+                  use dynamic_object::format::{NoDisplay as _, ProbeDisplay as _};
+                  unsafe { (&dynamic_object::format::Probe::<#name>::new()).display(object, f) }
+            },
+      };
+      if options.has("clone") {
+            functions.extend(quote! {
+                  clone: Some(|object| dynamic_object::__private::Box::new(<#name as Clone>::clone(#object))),
+            });
+      }
+      if options.has("lifecycle") {
+            functions.extend(quote! {
+                  on_init: Some(|object| <#name as dynamic_object::Lifecycle>::on_init(unsafe { &mut *(object as *mut #name) })),
+                  on_destroy: Some(|object| <#name as dynamic_object::Lifecycle>::on_destroy(unsafe { &mut *(object as *mut #name) })),
+            });
+      }
+      let mut markers = quote! {};
+      if options.has("eq") {
+            functions.extend(quote! {
+                  eq: Some(|object, other| <#name as PartialEq>::eq(#object, #other)),
+            });
+            markers.extend(quote! {
+                  impl dynamic_object::compare::ClassEq for #name {}
+            });
+      }
+      if options.has("hash") {
+            functions.extend(quote! {
+                  hash: Some(|object, mut state| <#name as core::hash::Hash>::hash(#object, &mut state)),
+            });
+            markers.extend(quote! {
+                  impl dynamic_object::compare::ClassHash for #name {}
+            });
+      }
+      if options.has("ord") {
+            functions.extend(quote! {
+                  cmp: Some(|object, other| <#name as Ord>::cmp(#object, #other)),
+            });
+            markers.extend(quote! {
+                  impl dynamic_object::compare::ClassOrd for #name {}
+            });
+      }
+      (functions, markers)
+}
+
+/// Deref to the parent and `as_parent`/`as_parent_mut` of a class with the `deref` option
+fn derefParent(item: &ItemStruct, parent: &Type, parentField: Option<&Ident>, options: &Options) -> __private::TokenStream2 {
+      if !options.has("deref") {
+            return quote! {};
+      }
+      let field = match parentField {
+            Some(field) => field,
+            None => return Error::new_spanned(&item.ident, "the `deref` option requires the field holding the parent").to_compile_error(),
+      };
+      let name = &item.ident;
+      let vis = &item.vis;
+      quote! {
+            impl core::ops::Deref for #name {
+                  type Target = #parent;
+
+                  fn deref(&self) -> &#parent {
+                        &self.#field
+                  }
+            }
+
+            impl core::ops::DerefMut for #name {
+                  fn deref_mut(&mut self) -> &mut #parent {
+                        &mut self.#field
+                  }
+            }
+
+            impl #name {
+                  /// The parent part of the struct
+                  #[allow(dead_code)]
+                  #vis fn as_parent(&self) -> &#parent {
+                        &self.#field
+                  }
+
+                  /// The parent part of the struct
+                  #[allow(dead_code)]
+                  #vis fn as_parent_mut(&mut self) -> &mut #parent {
+                        &mut self.#field
+                  }
+            }
+      }
+}
+
+/// Schema version of a class with the `version = N` option
+fn version(options: &Options) -> Result<Option<u32>> {
+      match options.value("version") {
+            Some(Lit::Int(version)) => {
+                  let version: u32 = version.base10_parse()?;
+                  if version == 0 {
+                        return Err(Error::new_spanned(options.value("version"), "versions start at 1"));
+                  }
+                  Ok(Some(version))
+            },
+            Some(version) => Err(Error::new_spanned(version, "expected an integer version")),
+            None => Ok(None),
+      }
+}
+
+/// SerdeClass::migrate, calling `Self::migrate_from_vK(Value) -> Self` for each version K before 'version'
+fn migrations(name: &Ident, version: u32) -> __private::TokenStream2 {
+      if version == 1 {
+            return quote! { None };
+      }
+      let froms: Vec<_> = (1..version).collect();
+      let migrations: Vec<_> = froms.iter().map(|from| format_ident!("migrate_from_v{}", from)).collect();
+      quote! {
+            Some(|from, old| {
+                  // Inherent migrate_from_vK functions take precedence over these
+                  #[allow(dead_code)]
+                  trait Migrations {
+                        #(
+                              fn #migrations(_: dynamic_object::value::Value) -> dynamic_object::serialization::NoMigration {
+                                    dynamic_object::serialization::NoMigration
+                              }
+                        )*
+                  }
+                  impl Migrations for #name {}
+
+                  trait Migrated {
+                        fn migrated(self) -> Option<#name>;
+                  }
+                  impl Migrated for #name {
+                        fn migrated(self) -> Option<#name> {
+                              Some(self)
+                        }
+                  }
+                  impl Migrated for dynamic_object::serialization::NoMigration {
+                        fn migrated(self) -> Option<#name> {
+                              None
+                        }
+                  }
+
+                  let migrated = match from {
+                        #(#froms => Migrated::migrated(<#name>::#migrations(old)),)*
+                        _ => None,
+                  };
+                  match migrated {
+                        Some(object) => dynamic_object::value::to_value(&object).map(Some),
+                        None => Ok(None),
+                  }
+            })
+      }
+}
+
+/// Registration with `dynamic_object::serialization` of a class with the `serde` or `serde_name = ".."` option
+fn serde(name: &Ident, options: &Options, version: Option<u32>) -> __private::TokenStream2 {
+      let serdeName = match options.value("serde_name") {
+            Some(serdeName) => quote! { #serdeName },
+            None if options.has("serde") => quote! { concat!(module_path!(), "::", stringify!(#name)) },
+            None if version.is_some() => {
+                  return Error::new_spanned(options.value("version"), "`version` requires the `serde` or `serde_name` option").to_compile_error();
+            },
+            None => return quote! {},
+      };
+      let migrate = migrations(name, version.unwrap_or(1));
+      quote! {
+            #[dynamic_object::__private::linkme::distributed_slice(dynamic_object::serialization::SERIALIZABLE)]
+            #[linkme(crate = dynamic_object::__private::linkme)]
+            static SERDE: dynamic_object::serialization::SerdeClass = dynamic_object::serialization::SerdeClass {
+                  class: <#name as dynamic_object::Class>::info,
+                  name: #serdeName,
+                  serialize: |object| object as *const #name as *const dyn dynamic_object::__private::erased_serde::Serialize,
+                  deserialize: |deserializer| {
+                        let object: #name = dynamic_object::__private::erased_serde::deserialize(deserializer)?;
+                        Ok(dynamic_object::__private::boxed::<#name, _>(object))
+                  },
+                  migrate: #migrate,
+            };
+      }
+}
+
+/// Seal of the class, checking that its parent is not sealed by another module
+///
+/// The `sealed` option is added by #[sealed_hierarchy] to the classes of its module
+fn sealed(name: &Ident, parent: &Type, options: &Options) -> __private::TokenStream2 {
+      let token = if options.has("sealed") {
+            quote! { __sealed::Token }
+      } else {
+            quote! { dynamic_object::__private::Open }
+      };
+      quote! {
+            impl dynamic_object::__private::Sealed for #name {
+                  type Token = #token;
+            }
+
+            #[allow(dead_code)]
+            fn sealed() {
+                  fn check<T: dynamic_object::__private::Extensible<#token>>() {}
+                  check::<<#parent as dynamic_object::__private::Sealed>::Token>();
+            }
+      }
+}
+
 #[proc_macro_attribute]
 pub fn subclass(args: TokenStream, tokens: TokenStream) -> TokenStream {
-      let argsCpy = args.clone();
-      let parse = parse_macro_input!(tokens as ItemStruct);
-      let parent = parse_macro_input!(args as Parent).parent;
+      let mut parse = parse_macro_input!(tokens as ItemStruct);
+      let args = parse_macro_input!(args as SubclassArgs);
+      let (parent, parentField, options) = match args.resolve(&parse) {
+            Ok(args) => args,
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+      };
+      let constructible = takeAttributes(&mut parse.attrs, "constructible");
+      let invariants = match invariants(&parse.ident, &takeAttributes(&mut parse.attrs, "invariant")) {
+            Ok(invariants) => invariants,
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+      };
       let name = &parse.ident;
 
-      let parentField = parse_macro_input!(argsCpy as ParentFieldName);
       let id = generateID(name);
-      let offsetof = offsetof(&parent, parentField);
+      let fields = fields(&parse, &parent, parentField.as_ref(), &options);
+      let asAny = if options.has("any") {
+            quote! {
+                  as_any: Some(|object| object as *const #name as *const dyn core::any::Any),
+                  as_any_mut: Some(|object| object as *mut #name as *mut dyn core::any::Any),
+            }
+      } else {
+            quote! { as_any: None, as_any_mut: None, }
+      };
+      let offsetof = offsetof(&parent, parentField.as_ref());
+      let layoutHash = layoutHash(&parse, &parent);
+
+      let (construct, constructItems) = constructor(name, &constructible);
+      let version = match version(&options) {
+            Ok(version) => version,
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+      };
+      let serde = serde(name, &options, version);
+      let (binary, binaryItems) = binary(&parse, &options);
+      let deref = derefParent(&parse, &parent, parentField.as_ref(), &options);
+      let builder = builder::expand(&parse, &parent, parentField.as_ref(), &options);
+      let (functions, compareMarkers) = functions(name, &options);
+      let sealed = sealed(name, &parent, &options);
+      let version = match version {
+            Some(version) => quote! { version: Some(#version), },
+            None => quote! {},
+      };
 
       let result = quote! {
             #parse
 
-            impl dynamic_object::Class for #name {
-                  type Parent = #parent;
-                  const NAME:&'static str = #id;
+            #builder
 
-                  fn isa(id: usize) -> bool {
-                        id == Self::id() || <Self as dynamic_object::Class>::Parent::isa(id)
-                  }
+            const _: () = {
+                  #[allow(clippy::needless_update)]
+                  static INFO: dynamic_object::ClassInfo = dynamic_object::ClassInfo {
+                        name: stringify!(#name),
+                        path: concat!(module_path!(), "::", stringify!(#name)),
+                        id: <#name as dynamic_object::Class>::id,
+                        isa: <#name as dynamic_object::Class>::isa,
+                        parent: <#parent as dynamic_object::Class>::info,
+                        offset: <#name as dynamic_object::Class>::offset,
+                        fields: #fields,
+                        layout_hash: <#name as dynamic_object::Class>::LAYOUT_HASH,
+                        #asAny
+                        #invariants
+                        #construct
+                        #functions
+                        #version
+                        #binary
+                        ..dynamic_object::ClassInfo::ROOT
+                  };
+
+                  #constructItems
+
+                  #[dynamic_object::__private::linkme::distributed_slice(dynamic_object::registry::CLASSES)]
+                  #[linkme(crate = dynamic_object::__private::linkme)]
+                  static REGISTER: &dynamic_object::ClassInfo = &INFO;
 
-                  // fn offsetof() -> usize;
-                  #offsetof
+                  #serde
 
-                  fn id() -> usize {
-                        Self::id as *const u8 as usize
+                  #binaryItems
+
+                  #compareMarkers
+
+                  #deref
+
+                  #sealed
+
+                  impl dynamic_object::Class for #name {
+                        type Parent = #parent;
+                        const NAME:&'static str = #id;
+                        const LAYOUT_HASH: u64 = #layoutHash;
+
+                        fn isa(id: usize) -> bool {
+                              id == Self::id() || <Self as dynamic_object::Class>::Parent::isa(id)
+                        }
+
+                        // fn offsetof() -> usize;
+                        #offsetof
+
+                        fn id() -> usize {
+                              Self::id as *const u8 as usize
+                        }
+
+                        fn info() -> &'static dynamic_object::ClassInfo {
+                              &INFO
+                        }
                   }
-            }
+            };
       };
       TokenStream::from(result)
 }
 
+/// Registers a constructor used by `dynamic_object::factory::Factory`
+///
+/// `#[constructible]` builds the class with `Default`, `#[constructible(path)]` calls `path(&Args) -> Self`
+#[proc_macro_attribute]
+pub fn constructible(args: TokenStream, tokens: TokenStream) -> TokenStream {
+      helperAttribute("constructible", args, tokens)
+}
+
+/// Registers an invariant checked by `dynamic_object::invariant`
+///
+/// `#[invariant(path)]` calls `path(&Self) -> bool`, it holds for subclasses too
+#[proc_macro_attribute]
+pub fn invariant(args: TokenStream, tokens: TokenStream) -> TokenStream {
+      helperAttribute("invariant", args, tokens)
+}
+
+/// Seals the class hierarchy declared in an inline module
+///
+/// Generates a `<Root>View` enum (or the name given as argument) with one variant per class
+/// and implements `dynamic_object::SealedHierarchy` for the root class
+///
+/// Subclasses of these classes declared outside of the module fail to compile
+#[proc_macro_attribute]
+pub fn sealed_hierarchy(args: TokenStream, tokens: TokenStream) -> TokenStream {
+      let mut module = parse_macro_input!(tokens as ItemMod);
+      let viewName = if args.is_empty() {
+            None
+      } else {
+            Some(parse_macro_input!(args as Ident))
+      };
+
+      let tree = match hierarchy::collect(&module) {
+            Ok(tree) => tree,
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+      };
+      hierarchy::seal(&mut module);
+      let root = &tree.nodes[tree.root].name;
+      let vis = &tree.nodes[tree.root].vis;
+      let view = viewName.unwrap_or_else(|| format_ident!("{}View", root));
+      let viewMut = format_ident!("{}Mut", view);
+
+      let classes: Vec<_> = tree.subtree(tree.root).into_iter()
+            .map(|i| &tree.nodes[i].name)
+            .collect();
+      let (leaves, last) = classes.split_at(classes.len() - 1);
+      let last = &last[0];
+
+      let generated: Vec<Item> = vec![
+            parse_quote! {
+                  #[doc(hidden)]
+                  mod __sealed {
+                        pub struct Token;
+                  }
+            },
+            parse_quote! {
+                  impl dynamic_object::__private::Extensible<__sealed::Token> for __sealed::Token {}
+            },
+            parse_quote! {
+                  #vis enum #view<'a> {
+                        #(#classes(&'a #classes),)*
+                  }
+            },
+            parse_quote! {
+                  #vis enum #viewMut<'a> {
+                        #(#classes(&'a mut #classes),)*
+                  }
+            },
+            parse_quote! {
+                  impl dynamic_object::SealedHierarchy for #root {
+                        type View<'a> = #view<'a>;
+                        type ViewMut<'a> = #viewMut<'a>;
+
+                        fn classify<C: core::ops::Deref>(object: &dynamic_object::Object<Self, C>) -> #view<'_> {
+                              #(
+                                    if object.isa::<#leaves>() {
+                                          return #view::#leaves(object.cast_ref::<#leaves>());
+                                    }
+                              )*
+                              #view::#last(object.cast_ref::<#last>())
+                        }
+
+                        fn classify_mut<C: core::ops::DerefMut>(object: &mut dynamic_object::Object<Self, C>) -> #viewMut<'_> {
+                              #(
+                                    if object.isa::<#leaves>() {
+                                          return #viewMut::#leaves(object.cast_mut::<#leaves>());
+                                    }
+                              )*
+                              #viewMut::#last(object.cast_mut::<#last>())
+                        }
+                  }
+            },
+      ];
+      if let Some((_, ref mut items)) = module.content {
+            items.extend(generated);
+      }
+
+      TokenStream::from(quote! { #module })
+}
+
+/// Setters of a class with the `builder` option, called by the macros it generates
+#[doc(hidden)]
+#[proc_macro]
+pub fn __builder(tokens: TokenStream) -> TokenStream {
+      let input = parse_macro_input!(tokens as builder::Setters);
+      TokenStream::from(builder::setters(input))
+}
+
+/// Dispatches on the dynamic type of an object
+///
+/// Arms are tried in the order they are written, so list derived classes before their parents:
+/// an arm following the arm of one of its parents can never match and warns.
+/// `match_class!(&object { .. })` binds `&T`, `&mut object` binds `&mut T` and `object` binds the cast object
+#[proc_macro]
+pub fn match_class(tokens: TokenStream) -> TokenStream {
+      let input = parse_macro_input!(tokens as match_class::MatchClass);
+      TokenStream::from(match_class::expand(input))
+}
+
+/// Generates visitors for the class hierarchy declared in an inline module
+///
+/// Adds a `Visitor` trait (or the name given as argument) with a `visit_<class>` method per class,
+/// its `VisitorMut` counterpart, and implements `dynamic_object::Visitable` for every class
+#[proc_macro_attribute]
+pub fn visitable(args: TokenStream, tokens: TokenStream) -> TokenStream {
+      let mut module = parse_macro_input!(tokens as ItemMod);
+      let visitor = if args.is_empty() {
+            format_ident!("Visitor")
+      } else {
+            parse_macro_input!(args as Ident)
+      };
+
+      let tree = match hierarchy::collect(&module) {
+            Ok(tree) => tree,
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+      };
+      let generated = visit::expand(&tree, &visitor);
+      if let Some((_, ref mut items)) = module.content {
+            items.extend(generated);
+      }
+
+      TokenStream::from(quote! { #module })
+}
+
+/// Registers the `&self` and `&mut self` methods of an impl block for `Object::invoke`
+///
+/// Generic, async and unsafe methods are skipped, as are methods without a `self` reference,
+/// methods taking references and methods marked `#[reflect(skip)]`.
+/// Every other parameter must implement `FromValue` and the return type `Into<Value>`
+#[proc_macro_attribute]
+pub fn reflect_methods(_: TokenStream, tokens: TokenStream) -> TokenStream {
+      let item = parse_macro_input!(tokens as ItemImpl);
+      TokenStream::from(method::expand(item))
+}
+
 #[proc_macro_attribute]
 pub fn module_name(_: TokenStream, stream: TokenStream) -> TokenStream {
       let parse = parse_macro_input!(stream as ItemStruct);
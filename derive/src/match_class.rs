@@ -0,0 +1,130 @@
+//! match_class! implementation
+use quote::{quote, quote_spanned};
+use syn::{*, __private::TokenStream2, parse::{Parse, ParseStream}, spanned::Spanned};
+
+enum Mode {
+      Ref,
+      Mut,
+      Owned,
+}
+
+enum Fallback {
+      Wildcard,
+      Binding(Ident),
+}
+
+struct Arm {
+      class: Path,
+      binding: Pat,
+      body: Expr,
+}
+
+pub struct MatchClass {
+      mode: Mode,
+      object: Expr,
+      arms: Vec<Arm>,
+      fallback: Option<(Fallback, Expr)>,
+}
+
+impl Parse for MatchClass {
+      fn parse(input: ParseStream) -> Result<Self> {
+            let (mode, object) = match Expr::parse_without_eager_brace(input)? {
+                  Expr::Reference(reference) => match reference.mutability {
+                        Some(_) => (Mode::Mut, *reference.expr),
+                        None => (Mode::Ref, *reference.expr),
+                  },
+                  object => (Mode::Owned, object),
+            };
+
+            let content;
+            braced!(content in input);
+            let mut arms = Vec::new();
+            let mut fallback = None;
+            while !content.is_empty() {
+                  if fallback.is_some() {
+                        return Err(content.error("no arm can follow the fallback arm"));
+                  }
+                  if content.peek(Token![_]) {
+                        content.parse::<Token![_]>()?;
+                        content.parse::<Token![=>]>()?;
+                        fallback = Some((Fallback::Wildcard, content.parse()?));
+                  } else {
+                        let class: Path = content.parse()?;
+                        if content.peek(token::Paren) {
+                              let binding;
+                              parenthesized!(binding in content);
+                              content.parse::<Token![=>]>()?;
+                              arms.push(Arm {
+                                    class,
+                                    binding: binding.parse()?,
+                                    body: content.parse()?,
+                              });
+                        } else {
+                              let name = class.get_ident()
+                                    .ok_or_else(|| Error::new_spanned(&class, "expected `Class(binding)`, `name` or `_`"))?
+                                    .clone();
+                              content.parse::<Token![=>]>()?;
+                              fallback = Some((Fallback::Binding(name), content.parse()?));
+                        }
+                  }
+                  if !content.is_empty() {
+                        content.parse::<Token![,]>()?;
+                  }
+            }
+
+            Ok(Self {
+                  mode,
+                  object,
+                  arms,
+                  fallback,
+            })
+      }
+}
+
+/// Warns if an earlier arm matches one of the parents of the class of the arm, so it could never match
+fn reachability(arms: &[Arm], index: usize) -> TokenStream2 {
+      let class = &arms[index].class;
+      let mut earlier = arms[..index].iter().map(|arm| &arm.class);
+      let unreachable = match earlier.next() {
+            Some(first) => quote! {
+                  dynamic_object::isSubclassOf::<#class, #first>()
+                  #(|| dynamic_object::isSubclassOf::<#class, #earlier>())*
+            },
+            None => return quote! {},
+      };
+      quote_spanned! { class.span() =>
+            dynamic_object::__private::Reachable::<{ #unreachable }>::check();
+      }
+}
+
+pub fn expand(input: MatchClass) -> TokenStream2 {
+      let object = &input.object;
+      let (scrutinee, cast) = match input.mode {
+            Mode::Ref => (quote! { &#object }, quote! { cast_ref }),
+            Mode::Mut => (quote! { &mut #object }, quote! { cast_mut }),
+            Mode::Owned => (quote! { #object }, quote! { cast }),
+      };
+
+      let checks: Vec<_> = (0..input.arms.len()).map(|i| reachability(&input.arms, i)).collect();
+      let arms = input.arms.iter().map(|Arm { class, binding, body }| {
+            quote! {
+                  if __object.isa::<#class>() {
+                        let #binding = __object.#cast::<#class>();
+                        #body
+                  }
+            }
+      });
+      let fallback = match input.fallback {
+            Some((Fallback::Wildcard, body)) => quote! { { #body } },
+            Some((Fallback::Binding(name), body)) => quote! { { let #name = __object; #body } },
+            None => quote! { {} },
+      };
+
+      quote! {
+            {
+                  #(#checks)*
+                  let __object = #scrutinee;
+                  #(#arms else)* #fallback
+            }
+      }
+}
@@ -0,0 +1,155 @@
+//! #[reflect_methods]
+use syn::*;
+use syn::__private::TokenStream2;
+use quote::{quote, format_ident};
+
+/// Name of a parameter as written, "_" for patterns
+fn paramName(pat: &Pat) -> String {
+      match pat {
+            Pat::Ident(ident) => ident.ident.to_string(),
+            _ => "_".to_string(),
+      }
+}
+
+/// Remove the #[reflect(skip)] attributes of a method, true if it had one
+fn takeSkip(method: &mut ImplItemMethod) -> Result<bool> {
+      let mut skip = false;
+      let mut error = None;
+      method.attrs.retain(|attr| {
+            if !attr.path.is_ident("reflect") {
+                  return true;
+            }
+            match attr.parse_args::<Ident>() {
+                  Ok(arg) if arg == "skip" => skip = true,
+                  _ => error = Some(Error::new_spanned(attr, "expected #[reflect(skip)]")),
+            }
+            false
+      });
+      match error {
+            Some(error) => Err(error),
+            None => Ok(skip),
+      }
+}
+
+/// Whether a parameter of this type can never be read from a Value, which holds owned values:
+/// it borrows, anywhere in its type arguments, or names `Self`, which the generated code cannot
+fn unsupportedParam(ty: &Type) -> bool {
+      match ty {
+            Type::Path(path) => path.qself.is_some() || path.path.segments.iter().any(|segment| {
+                  segment.ident == "Self" || match &segment.arguments {
+                        PathArguments::None => false,
+                        PathArguments::AngleBracketed(args) => args.args.iter().any(|arg| match arg {
+                              GenericArgument::Type(ty) => unsupportedParam(ty),
+                              GenericArgument::Binding(binding) => unsupportedParam(&binding.ty),
+                              _ => false,
+                        }),
+                        PathArguments::Parenthesized(_) => true,
+                  }
+            }),
+            Type::Tuple(tuple) => tuple.elems.iter().any(unsupportedParam),
+            Type::Array(array) => unsupportedParam(&array.elem),
+            Type::Slice(slice) => unsupportedParam(&slice.elem),
+            Type::Paren(paren) => unsupportedParam(&paren.elem),
+            Type::Group(group) => unsupportedParam(&group.elem),
+            _ => true,
+      }
+}
+
+/// MethodInfo of a method taking &self or &mut self, None for the others
+fn method(item: &ItemImpl, method: &ImplItemMethod) -> Option<TokenStream2> {
+      let sig = &method.sig;
+      if !sig.generics.params.is_empty() || sig.asyncness.is_some() || sig.unsafety.is_some() {
+            return None;
+      }
+      let mutable = match sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() => receiver.mutability.is_some(),
+            _ => return None,
+      };
+
+      let selfTy = &item.self_ty;
+      let name = &sig.ident;
+      let params: Vec<_> = sig.inputs.iter().skip(1).filter_map(|arg| match arg {
+            FnArg::Typed(arg) => Some(arg),
+            FnArg::Receiver(_) => None,
+      }).collect();
+      if params.iter().any(|param| unsupportedParam(&param.ty)) {
+            return None;
+      }
+      let count = params.len();
+      let names = params.iter().map(|param| paramName(&param.pat));
+      let types: Vec<_> = params.iter().map(|param| &param.ty).collect();
+      let args: Vec<_> = (0..count).map(|i| format_ident!("arg{}", i)).collect();
+      let indices = 0..count;
+      let returns = match sig.output {
+            ReturnType::Default => quote! { "()" },
+            ReturnType::Type(_, ref ty) => quote! { stringify!(#ty) },
+      };
+
+      let function = match item.trait_ {
+            Some((_, ref path, _)) => quote! { <#selfTy as #path>::#name },
+            None => quote! { <#selfTy>::#name },
+      };
+      let object = if mutable {
+            quote! { &mut *(object as *mut #selfTy) }
+      } else {
+            quote! { &*(object as *const #selfTy) }
+      };
+      let register = format_ident!("METHOD_{}", name);
+
+      Some(quote! {
+            #[dynamic_object::__private::linkme::distributed_slice(dynamic_object::method::METHODS)]
+            #[linkme(crate = dynamic_object::__private::linkme)]
+            #[allow(non_upper_case_globals)]
+            static #register: dynamic_object::method::MethodInfo = dynamic_object::method::MethodInfo {
+                  class: <#selfTy as dynamic_object::Class>::info,
+                  name: stringify!(#name),
+                  params: &[#((#names, stringify!(#types))),*],
+                  returns: #returns,
+                  mutable: #mutable,
+                  invoke: |object, args| {
+                        if args.len() != #count {
+                              return Err(dynamic_object::method::InvokeError::ArgumentCount { expected: #count, found: args.len() });
+                        }
+                        #(
+                              let #args: #types = match dynamic_object::value::FromValue::from_value(&args[#indices]) {
+                                    Some(arg) => arg,
+                                    None => return Err(dynamic_object::method::InvokeError::ArgumentType {
+                                          index: #indices,
+                                          expected: stringify!(#types),
+                                          found: args[#indices].type_name(),
+                                    }),
+                              };
+                        )*
+                        let object = unsafe { #object };
+                        Ok(dynamic_object::value::Value::from(#function(object, #(#args),*)))
+                  },
+            };
+      })
+}
+
+pub fn expand(mut item: ItemImpl) -> TokenStream2 {
+      if !item.generics.params.is_empty() {
+            return Error::new_spanned(&item.generics, "#[reflect_methods] does not support generic impl blocks").to_compile_error();
+      }
+      let mut skipped = Vec::new();
+      for member in &mut item.items {
+            if let ImplItem::Method(member) = member {
+                  match takeSkip(member) {
+                        Ok(true) => skipped.push(member.sig.ident.clone()),
+                        Ok(false) => {},
+                        Err(err) => return err.to_compile_error(),
+                  }
+            }
+      }
+      let methods: Vec<_> = item.items.iter().filter_map(|member| match member {
+            ImplItem::Method(member) if !skipped.contains(&member.sig.ident) => method(&item, member),
+            _ => None,
+      }).collect();
+      quote! {
+            #item
+
+            const _: () = {
+                  #(#methods)*
+            };
+      }
+}
@@ -0,0 +1,106 @@
+//! #[visitable] implementation
+use quote::{quote, format_ident};
+use syn::*;
+
+use crate::hierarchy::Hierarchy;
+
+/// BinaryOp -> binary_op, HTTPServer -> http_server
+fn snakeCase(name: &Ident) -> String {
+      let chars: Vec<char> = name.to_string().chars().collect();
+      let mut result = String::new();
+      for (i, &c) in chars.iter().enumerate() {
+            if c.is_uppercase() && i != 0 {
+                  // A run of capitals is one word, the last capital starting the next one: HTTPServer is http_server
+                  let previous = chars[i - 1];
+                  let next = chars.get(i + 1).copied();
+                  if (!previous.is_uppercase() && previous != '_') || (previous.is_uppercase() && next.is_some_and(char::is_lowercase)) {
+                        result.push('_');
+                  }
+            }
+            result.extend(c.to_lowercase());
+      }
+      result
+}
+
+/// Generates the Visitor/VisitorMut traits and the Visitable impls of every class in 'tree'
+pub fn expand(tree: &Hierarchy, visitor: &Ident) -> Vec<Item> {
+      let visitorMut = format_ident!("{}Mut", visitor);
+      let vis = &tree.nodes[tree.root].vis;
+      let visit: Vec<_> = tree.nodes.iter()
+            .map(|node| format_ident!("visit_{}", snakeCase(&node.name)))
+            .collect();
+      let visitMut: Vec<_> = visit.iter().map(|visit| format_ident!("{}_mut", visit)).collect();
+
+      let mut methods = Vec::new();
+      let mut methodsMut = Vec::new();
+      for (i, node) in tree.nodes.iter().enumerate() {
+            let name = &node.name;
+            let (method, methodMut) = (&visit[i], &visitMut[i]);
+            let (body, bodyMut) = match (node.parent, &node.parentField) {
+                  (Some(parent), Some(field)) => {
+                        let (parent, parentMut) = (&visit[parent], &visitMut[parent]);
+                        (quote! { self.#parent(&object.#field) }, quote! { self.#parentMut(&mut object.#field) })
+                  },
+                  _ => (quote! { let _ = object; }, quote! { let _ = object; }),
+            };
+            methods.push(quote! {
+                  fn #method(&mut self, object: &#name) {
+                        #body
+                  }
+            });
+            methodsMut.push(quote! {
+                  fn #methodMut(&mut self, object: &mut #name) {
+                        #bodyMut
+                  }
+            });
+      }
+
+      let mut items: Vec<Item> = vec![
+            parse_quote! {
+                  #vis trait #visitor {
+                        #(#methods)*
+                  }
+            },
+            parse_quote! {
+                  #vis trait #visitorMut {
+                        #(#methodsMut)*
+                  }
+            },
+      ];
+
+      for (i, node) in tree.nodes.iter().enumerate() {
+            let name = &node.name;
+            let subtree = tree.subtree(i);
+            let (leaves, last) = subtree.split_at(subtree.len() - 1);
+            let classes: Vec<_> = leaves.iter().map(|&j| &tree.nodes[j].name).collect();
+            let leafVisit: Vec<_> = leaves.iter().map(|&j| &visit[j]).collect();
+            let leafVisitMut: Vec<_> = leaves.iter().map(|&j| &visitMut[j]).collect();
+            let (lastVisit, lastVisitMut) = (&visit[last[0]], &visitMut[last[0]]);
+
+            items.push(parse_quote! {
+                  impl<V: #visitor + ?Sized> dynamic_object::Visitable<V> for #name {
+                        fn accept<C: core::ops::Deref>(object: &dynamic_object::Object<Self, C>, visitor: &mut V) {
+                              #(
+                                    if object.isa::<#classes>() {
+                                          return visitor.#leafVisit(object.cast_ref::<#classes>());
+                                    }
+                              )*
+                              visitor.#lastVisit(&**object)
+                        }
+                  }
+            });
+            items.push(parse_quote! {
+                  impl<V: #visitorMut + ?Sized> dynamic_object::VisitableMut<V> for #name {
+                        fn accept_mut<C: core::ops::DerefMut>(object: &mut dynamic_object::Object<Self, C>, visitor: &mut V) {
+                              #(
+                                    if object.isa::<#classes>() {
+                                          return visitor.#leafVisitMut(object.cast_mut::<#classes>());
+                                    }
+                              )*
+                              visitor.#lastVisitMut(&mut **object)
+                        }
+                  }
+            });
+      }
+      items
+}
@@ -0,0 +1,40 @@
+//! Items used by the code generated in dynamic_object_derive
+//! Not public API
+
+pub use linkme;
+pub use alloc::boxed::Box;
+
+use crate::{Class, Object};
+#[cfg(feature = "serde")]
+pub use erased_serde;
+
+/// Implemented by #[subclass]: 'Token' is private to the module of a #[sealed_hierarchy], Open otherwise
+pub trait Sealed {
+      type Token;
+}
+
+/// Token of the classes outside of any #[sealed_hierarchy]
+pub struct Open;
+
+/// Implemented when a class sealed by 'Token' may inherit from a class sealed by Self
+#[diagnostic::on_unimplemented(message = "cannot inherit from a class of a #[sealed_hierarchy] outside of its module")]
+pub trait Extensible<Token> {}
+
+impl<Token> Extensible<Token> for Open {}
+
+/// `check` is deprecated when an arm of match_class! can never match, to warn about it
+pub struct Reachable<const UNREACHABLE: bool>;
+
+impl Reachable<false> {
+      pub const fn check() {}
+}
+
+impl Reachable<true> {
+      #[deprecated(note = "unreachable match_class! arm: an earlier arm matches a parent class")]
+      pub const fn check() {}
+}
+
+/// Box 'value' as an object seen as 'Base', without checking the invariants: loaders report them as errors
+pub fn boxed<T: Class + 'static, Base: Class>(value: T) -> Object<Base> {
+      Object::<T>::fromContainer(Box::new(value)).reinterpret()
+}
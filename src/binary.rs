@@ -0,0 +1,681 @@
+//! Compact binary persistence
+//!
+//! Classes opt in with `#[subclass(.., binary)]`, which encodes their fields one after another
+//! with [`Encode`] and [`Decode`]. Parent fields and fields holding objects need them too.
+//!
+//! Format, integers in little endian and lengths as LEB128:
+//! - the magic bytes "DYNO" and the format version
+//! - the class table: a count, then the id, the path and the schema hash of each class used
+//! - the objects: a count, then each object as the id of its dynamic class,
+//!   the length of its payload and the payload
+//!
+//! Objects held by fields are written inline in the same way.
+//! The schema hash of a class, [`Decode::SCHEMA`], covers the names, order and encoded types of its fields,
+//! so it changes only if the encoding does, unlike its [layout hash](crate::layout).
+//! Reading fails if a class of the table has another schema hash in this build.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase, binary)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! #[subclass(Class, parent, binary)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let object = Object::<Derived>::new(Box::new(Derived { field: 1, parent: Class { value: 2, foo: 3 } }));
+//! let bytes = binary::write(&object.cast::<Class>()).unwrap();
+//!
+//! let object = binary::read::<Class>(&bytes).unwrap();
+//! assert!(object.value == 2);
+//! assert!(object.cast::<Derived>().field == 1);
+//! ```
+use core::{fmt, ops::Deref};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+
+use crate::{invariant::InvariantError, layout, registry, Class, ClassInfo, DynamicObjectBase, Object};
+
+const MAGIC: &[u8; 4] = b"DYNO";
+const FORMAT_VERSION: u8 = 3;
+
+/// Decodes the most-derived struct of an object
+pub type DecodeFn = fn(reader: &mut Reader) -> Result<Object<DynamicObjectBase>, Error>;
+
+/// Why objects could not be written or read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+      /// The class, given by path, does not have the `binary` option
+      NotEncodable(&'static str),
+      /// No class of this path is linked in the program, or it does not have the `binary` option
+      UnknownClass(String),
+      /// The class, given by path, was written with another schema
+      SchemaMismatch(String),
+      /// An object is not of the requested class
+      NotSubclass,
+      /// An object read breaks an invariant of its class
+      Invariant(InvariantError),
+      UnexpectedEnd,
+      Invalid(&'static str),
+}
+
+impl fmt::Display for Error {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  Error::NotEncodable(class) => write!(f, "class {} does not have the binary option", class),
+                  Error::UnknownClass(class) => write!(f, "unknown class {}", class),
+                  Error::SchemaMismatch(class) => write!(f, "class {} was written with another schema", class),
+                  Error::NotSubclass => write!(f, "object does not inherit from the requested class"),
+                  Error::Invariant(err) => write!(f, "{}", err),
+                  Error::UnexpectedEnd => write!(f, "unexpected end of data"),
+                  Error::Invalid(reason) => write!(f, "invalid data: {}", reason),
+            }
+      }
+}
+
+/// Writes values, collecting the classes of the objects written
+pub struct Writer {
+      buffer: Vec<u8>,
+      classes: Vec<&'static ClassInfo>,
+}
+
+impl Writer {
+      fn new() -> Self {
+            Self {
+                  buffer: Vec::new(),
+                  classes: Vec::new(),
+            }
+      }
+
+      pub fn write_bytes(&mut self, bytes: &[u8]) {
+            self.buffer.extend_from_slice(bytes);
+      }
+
+      /// Write 'value' as LEB128
+      pub fn write_length(&mut self, mut value: u64) {
+            loop {
+                  let byte = (value & 0x7f) as u8;
+                  value >>= 7;
+                  if value == 0 {
+                        self.buffer.push(byte);
+                        return;
+                  }
+                  self.buffer.push(byte | 0x80);
+            }
+      }
+
+      /// Write the object at 'object', of class 'class', with its class id and length
+      fn write_object(&mut self, class: &'static ClassInfo, object: *const u8) -> Result<(), Error> {
+            let encode = class.encode.ok_or(Error::NotEncodable(class.path))?;
+            let id = match self.classes.iter().position(|known| *known == class) {
+                  Some(id) => id,
+                  None => {
+                        self.classes.push(class);
+                        self.classes.len() - 1
+                  }
+            };
+            let outer = core::mem::take(&mut self.buffer);
+            let result = unsafe { &*encode(object) }.encode(self);
+            let payload = core::mem::replace(&mut self.buffer, outer);
+            result?;
+            self.write_length(id as u64);
+            self.write_length(payload.len() as u64);
+            self.write_bytes(&payload);
+            Ok(())
+      }
+}
+
+/// A class of the class table
+struct Entry<'a> {
+      path: &'a str,
+      class: Option<&'static ClassInfo>,
+}
+
+/// Reads values written by a [`Writer`]
+pub struct Reader<'a> {
+      bytes: &'a [u8],
+      classes: &'a BTreeMap<u64, Entry<'a>>,
+}
+
+impl<'a> Reader<'a> {
+      pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+            if self.bytes.len() < len {
+                  return Err(Error::UnexpectedEnd);
+            }
+            let (bytes, rest) = self.bytes.split_at(len);
+            self.bytes = rest;
+            Ok(bytes)
+      }
+
+      /// Read a LEB128 value
+      pub fn read_length(&mut self) -> Result<u64, Error> {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                  let byte = self.read_bytes(1)?[0];
+                  value |= ((byte & 0x7f) as u64) << shift;
+                  if byte & 0x80 == 0 {
+                        return Ok(value);
+                  }
+            }
+            Err(Error::Invalid("length too long"))
+      }
+
+      /// Read a LEB128 length fitting in the remaining data
+      fn read_len(&mut self) -> Result<usize, Error> {
+            let len = self.read_length()?;
+            if len > self.bytes.len() as u64 {
+                  return Err(Error::UnexpectedEnd);
+            }
+            Ok(len as usize)
+      }
+
+      fn read_object(&mut self) -> Result<Object<DynamicObjectBase>, Error> {
+            let id = self.read_length()?;
+            let entry = self.classes.get(&id).ok_or(Error::Invalid("unknown class id"))?;
+            let decode = entry.class
+                  .and_then(|class| class.decode)
+                  .ok_or_else(|| Error::UnknownClass(entry.path.into()))?;
+            let len = self.read_len()?;
+            let mut payload = Reader {
+                  bytes: self.read_bytes(len)?,
+                  classes: self.classes,
+            };
+            let object = decode(&mut payload)?;
+            if !payload.bytes.is_empty() {
+                  return Err(Error::Invalid("object payload longer than its fields"));
+            }
+            object.check_invariants().map_err(Error::Invariant)?;
+            Ok(object)
+      }
+}
+
+/// Encoding of a value, generated for classes by the `binary` option
+pub trait Encode {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error>;
+}
+
+/// Decoding of a value, generated for classes by the `binary` option
+pub trait Decode: Sized {
+      /// Fingerprint of the encoding of the type, made with [`schema`] from those of the types it is made of
+      /// Recursive types cannot use their own
+      const SCHEMA: u64;
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error>;
+}
+
+/// Fingerprint of an encoding named 'name' made of the encodings 'parts'
+pub const fn schema(name: &str, parts: &[u64]) -> u64 {
+      let mut hash = layout::hash_str(layout::ROOT, name);
+      let mut i = 0;
+      while i < parts.len() {
+            hash = layout::hash_u64(hash, parts[i]);
+            i += 1;
+      }
+      hash
+}
+
+/// Write objects to a new buffer
+pub fn write_all<'a, T: Class + 'a, C: Deref + 'a>(objects: impl IntoIterator<Item = &'a Object<T, C>>) -> Result<Vec<u8>, Error> {
+      let mut body = Writer::new();
+      let mut count = 0;
+      for object in objects {
+            body.write_object(object.class, object.base())?;
+            count += 1;
+      }
+
+      let mut writer = Writer::new();
+      writer.write_bytes(MAGIC);
+      writer.write_bytes(&[FORMAT_VERSION]);
+      writer.write_length(body.classes.len() as u64);
+      for (id, class) in body.classes.iter().enumerate() {
+            writer.write_length(id as u64);
+            class.path.encode(&mut writer)?;
+            class.schema.encode(&mut writer)?;
+      }
+      writer.write_length(count);
+      writer.write_bytes(&body.buffer);
+      Ok(writer.buffer)
+}
+
+/// Write an object to a new buffer
+pub fn write<T: Class, C: Deref>(object: &Object<T, C>) -> Result<Vec<u8>, Error> {
+      write_all([object])
+}
+
+/// Read objects written by `write_all`, failing if any of them is not a 'T'
+pub fn read_all<T: Class>(bytes: &[u8]) -> Result<Vec<Object<T>>, Error> {
+      let empty = BTreeMap::new();
+      let mut header = Reader {
+            bytes,
+            classes: &empty,
+      };
+      if header.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::Invalid("not a dynamic_object binary"));
+      }
+      if header.read_bytes(1)?[0] != FORMAT_VERSION {
+            return Err(Error::Invalid("unsupported format version"));
+      }
+
+      let mut classes = BTreeMap::new();
+      for _ in 0..header.read_length()? {
+            let id = header.read_length()?;
+            let len = header.read_len()?;
+            let path = core::str::from_utf8(header.read_bytes(len)?).map_err(|_| Error::Invalid("class path is not UTF-8"))?;
+            let schema = u64::decode(&mut header)?;
+            let class = registry::all().find(|class| class.path() == path);
+            if class.is_some_and(|class| class.decode.is_some() && class.schema != schema) {
+                  return Err(Error::SchemaMismatch(path.into()));
+            }
+            classes.insert(id, Entry { path, class });
+      }
+
+      let mut reader = Reader {
+            bytes: header.bytes,
+            classes: &classes,
+      };
+      let count = reader.read_length()?;
+      let mut objects = Vec::new();
+      for _ in 0..count {
+            let object = reader.read_object()?;
+            if !object.isa::<T>() {
+                  return Err(Error::NotSubclass);
+            }
+            objects.push(object.cast());
+      }
+      if !reader.bytes.is_empty() {
+            return Err(Error::Invalid("trailing data"));
+      }
+      Ok(objects)
+}
+
+/// Read an object written by `write`
+pub fn read<T: Class>(bytes: &[u8]) -> Result<Object<T>, Error> {
+      let mut objects = read_all(bytes)?;
+      if objects.len() != 1 {
+            return Err(Error::Invalid("expected a single object"));
+      }
+      Ok(objects.remove(0))
+}
+
+impl<T: Class, C: Deref> Encode for Object<T, C> {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_object(self.class, self.base())
+      }
+}
+
+impl<T: Class> Decode for Object<T> {
+      // Each object is checked against the schema of its own class
+      const SCHEMA: u64 = schema("Object", &[]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            let object = reader.read_object()?;
+            if !object.isa::<T>() {
+                  return Err(Error::NotSubclass);
+            }
+            Ok(object.cast())
+      }
+}
+
+macro_rules! number {
+      ($($type:ty),*) => {
+            $(
+                  impl Encode for $type {
+                        fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+                              writer.write_bytes(&self.to_le_bytes());
+                              Ok(())
+                        }
+                  }
+
+                  impl Decode for $type {
+                        const SCHEMA: u64 = schema(stringify!($type), &[]);
+
+                        fn decode(reader: &mut Reader) -> Result<Self, Error> {
+                              let bytes = reader.read_bytes(core::mem::size_of::<$type>())?;
+                              Ok(<$type>::from_le_bytes(bytes.try_into().unwrap()))
+                        }
+                  }
+            )*
+      };
+}
+
+number!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, f32, f64);
+
+impl Encode for usize {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_length(*self as u64);
+            Ok(())
+      }
+}
+
+impl Decode for usize {
+      const SCHEMA: u64 = schema("usize", &[]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            usize::try_from(reader.read_length()?).map_err(|_| Error::Invalid("usize out of range"))
+      }
+}
+
+impl Encode for isize {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            (*self as i64).encode(writer)
+      }
+}
+
+impl Decode for isize {
+      const SCHEMA: u64 = schema("isize", &[]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            isize::try_from(i64::decode(reader)?).map_err(|_| Error::Invalid("isize out of range"))
+      }
+}
+
+impl Encode for bool {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_bytes(&[*self as u8]);
+            Ok(())
+      }
+}
+
+impl Decode for bool {
+      const SCHEMA: u64 = schema("bool", &[]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            match reader.read_bytes(1)?[0] {
+                  0 => Ok(false),
+                  1 => Ok(true),
+                  _ => Err(Error::Invalid("invalid bool")),
+            }
+      }
+}
+
+impl Encode for char {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            (*self as u32).encode(writer)
+      }
+}
+
+impl Decode for char {
+      const SCHEMA: u64 = schema("char", &[]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            char::from_u32(u32::decode(reader)?).ok_or(Error::Invalid("invalid char"))
+      }
+}
+
+impl Encode for () {
+      fn encode(&self, _: &mut Writer) -> Result<(), Error> {
+            Ok(())
+      }
+}
+
+impl Decode for () {
+      const SCHEMA: u64 = schema("()", &[]);
+
+      fn decode(_: &mut Reader) -> Result<Self, Error> {
+            Ok(())
+      }
+}
+
+impl Encode for str {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_length(self.len() as u64);
+            writer.write_bytes(self.as_bytes());
+            Ok(())
+      }
+}
+
+impl Encode for String {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            self.as_str().encode(writer)
+      }
+}
+
+impl Decode for String {
+      const SCHEMA: u64 = schema("String", &[]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            let len = reader.read_len()?;
+            let bytes = reader.read_bytes(len)?;
+            core::str::from_utf8(bytes).map(String::from).map_err(|_| Error::Invalid("string is not UTF-8"))
+      }
+}
+
+impl<T: Encode> Encode for [T] {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            writer.write_length(self.len() as u64);
+            self.iter().try_for_each(|value| value.encode(writer))
+      }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            self.as_slice().encode(writer)
+      }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+      // Same encoding as slices
+      const SCHEMA: u64 = schema("[]", &[T::SCHEMA]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            let len = reader.read_len()?;
+            (0..len).map(|_| T::decode(reader)).collect()
+      }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            self.iter().try_for_each(|value| value.encode(writer))
+      }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+      const SCHEMA: u64 = schema("[; N]", &[T::SCHEMA, N as u64]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            let values = (0..N).map(|_| T::decode(reader)).collect::<Result<Vec<_>, _>>()?;
+            Ok(values.try_into().unwrap_or_else(|_| unreachable!()))
+      }
+}
+
+impl<T: Encode> Encode for Option<T> {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            match self {
+                  Some(value) => {
+                        writer.write_bytes(&[1]);
+                        value.encode(writer)
+                  },
+                  None => {
+                        writer.write_bytes(&[0]);
+                        Ok(())
+                  },
+            }
+      }
+}
+
+impl<T: Decode> Decode for Option<T> {
+      const SCHEMA: u64 = schema("Option", &[T::SCHEMA]);
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            match bool::decode(reader)? {
+                  true => T::decode(reader).map(Some),
+                  false => Ok(None),
+            }
+      }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+      fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+            (**self).encode(writer)
+      }
+}
+
+impl<T: Decode> Decode for Box<T> {
+      // Encoded as its content
+      const SCHEMA: u64 = T::SCHEMA;
+
+      fn decode(reader: &mut Reader) -> Result<Self, Error> {
+            T::decode(reader).map(Box::new)
+      }
+}
+
+macro_rules! tuple {
+      ($(($($name:ident),*)),*) => {
+            $(
+                  impl<$($name: Encode),*> Encode for ($($name,)*) {
+                        fn encode(&self, writer: &mut Writer) -> Result<(), Error> {
+                              let ($($name,)*) = self;
+                              $($name.encode(writer)?;)*
+                              Ok(())
+                        }
+                  }
+
+                  impl<$($name: Decode),*> Decode for ($($name,)*) {
+                        const SCHEMA: u64 = schema("()", &[$($name::SCHEMA),*]);
+
+                        fn decode(reader: &mut Reader) -> Result<Self, Error> {
+                              Ok(($($name::decode(reader)?,)*))
+                        }
+                  }
+            )*
+      };
+}
+
+tuple!((A), (A, B), (A, B, C), (A, B, C, D));
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, Class as _, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase, binary)]
+      struct Class {
+            value: u32,
+            foo: u32
+      }
+
+      #[subclass(Class, parent, binary)]
+      struct Derived {
+            field: u32,
+            parent: Class,
+      }
+
+      #[subclass(Class, parent, binary)]
+      struct Container {
+            parent: Class,
+            name: String,
+            children: Vec<Object<Class>>,
+            best: Option<Object<Class>>,
+            pair: (bool, char),
+            grid: [i16; 3],
+      }
+
+      #[subclass(Class, parent)]
+      struct Plain {
+            parent: Class
+      }
+
+      fn derived(field: u32) -> Object<Class> {
+            Object::<Derived>::new(Box::new(Derived { field, parent: Class { value: field + 1, foo: field + 2 } })).cast()
+      }
+
+      #[test]
+      fn round_trip() {
+            let objects = vec![
+                  derived(10),
+                  Object::<Class>::new(Box::new(Class { value: 1, foo: 2 })),
+                  Object::<Container>::new(Box::new(Container {
+                        parent: Class { value: 3, foo: 4 },
+                        name: "container".into(),
+                        children: vec![derived(20), derived(30)],
+                        best: Some(derived(40)),
+                        pair: (true, 'é'),
+                        grid: [-1, 0, 1],
+                  })).cast(),
+            ];
+            let bytes = write_all(&objects).unwrap();
+            let objects = read_all::<Class>(&bytes).unwrap();
+            assert!(objects.len() == 3);
+
+            let first = objects[0].cast_ref::<Derived>();
+            assert!(first.field == 10 && first.parent.value == 11 && first.parent.foo == 12);
+            assert!(objects[1].class() == Class::info() && objects[1].foo == 2);
+
+            let container = objects[2].cast_ref::<Container>();
+            assert!(container.parent.value == 3 && container.name == "container");
+            assert!(container.children[1].cast_ref::<Derived>().field == 30);
+            assert!(container.best.as_ref().unwrap().value == 41);
+            assert!(container.pair == (true, 'é') && container.grid == [-1, 0, 1]);
+      }
+
+      #[test]
+      fn errors() {
+            let plain = Object::<Plain>::new(Box::new(Plain { parent: Class { value: 0, foo: 0 } }));
+            assert!(write(&plain) == Err(Error::NotEncodable(Plain::info().path())));
+
+            let bytes = write(&derived(1)).unwrap();
+            assert!(read::<Derived>(&bytes).is_ok());
+            assert!(read::<Container>(&bytes).err() == Some(Error::NotSubclass));
+            assert!(read::<Class>(&bytes[..bytes.len() - 1]).err() == Some(Error::UnexpectedEnd));
+            assert!(read::<Class>(b"JSON").is_err());
+
+            let path = Derived::info().path().as_bytes();
+            let renamed: Vec<u8> = bytes.windows(path.len()).position(|window| window == path)
+                  .map(|start| [&bytes[..start], b"x", &bytes[start + 1..]].concat())
+                  .unwrap();
+            assert!(matches!(read::<Class>(&renamed), Err(Error::UnknownClass(_))));
+
+            let hash = Derived::SCHEMA.to_le_bytes();
+            let drifted: Vec<u8> = bytes.windows(hash.len()).position(|window| window == hash)
+                  .map(|start| [&bytes[..start], &[!hash[0]], &bytes[start + 1..]].concat())
+                  .unwrap();
+            assert!(read::<Class>(&drifted).err() == Some(Error::SchemaMismatch(Derived::info().path().into())));
+      }
+
+      mod aligned {
+            use super::*;
+
+            // Same encoding as Class, another layout
+            #[subclass(DynamicObjectBase, binary)]
+            #[repr(C, align(16))]
+            pub struct Class {
+                  value: core::primitive::u32,
+                  foo: u32
+            }
+
+            #[subclass(DynamicObjectBase, binary)]
+            pub struct Swapped {
+                  foo: u32,
+                  value: u32,
+            }
+
+            #[subclass(DynamicObjectBase, binary)]
+            pub struct Wider {
+                  value: u64,
+                  foo: u32
+            }
+      }
+
+      fn schemaOf<T: Decode>() -> u64 {
+            T::SCHEMA
+      }
+
+      #[test]
+      fn schema() {
+            assert!(schemaOf::<aligned::Class>() == schemaOf::<super::test::Class>());
+            assert!(aligned::Class::info().layout_hash() != super::test::Class::info().layout_hash());
+            assert!(schemaOf::<aligned::Swapped>() != schemaOf::<super::test::Class>());
+            assert!(schemaOf::<aligned::Wider>() != schemaOf::<super::test::Class>());
+            assert!(schemaOf::<Vec<Option<u32>>>() != schemaOf::<Vec<u32>>());
+            assert!(schemaOf::<Box<u32>>() == schemaOf::<u32>());
+      }
+}
@@ -0,0 +1,178 @@
+//! Flat builders
+//!
+//! `#[subclass(.., builder)]` generates `Class::builder()`, returning a `ClassBuilder` struct with a setter per field
+//! of the class and of its parents, so the fields of every level are set on the same builder without any import.
+//! Fields not set keep their `Default`, so the type of every field must implement `Default`,
+//! and the parent needs the `builder` option too, in the same crate: it declares a hidden macro under its own name
+//! giving its setters to its subclasses.
+//! A field with the name of a field of a parent would make the setters ambiguous and fails to compile:
+//! ```compile_fail
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase, builder)]
+//! struct Class {
+//!       value: u32
+//! }
+//!
+//! #[subclass(Class, parent, builder)]
+//! struct Derived {
+//!       value: u32,
+//!       parent: Class,
+//! }
+//! ```
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase, builder)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! #[subclass(Class, parent, builder)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let object = Derived::builder().value(1).field(3).build_object();
+//! assert!(object.parent.value == 1 && object.parent.foo == 0 && object.field == 3);
+//! ```
+use core::marker::PhantomData;
+use alloc::boxed::Box;
+
+use crate::{isSubclassOf, reflect::levels, Class, Object};
+
+/// Setter 'INDEX' of a class with the `builder` option, used by the builders of its subclasses
+#[doc(hidden)]
+pub trait Field<const INDEX: usize> {
+      type Type;
+
+      fn set(&mut self, value: Self::Type);
+}
+
+/// Implemented by classes with the `builder` option
+pub trait Build: Class + Sized {
+      /// Names of the setters of the class and of its parents
+      const SETTERS: &'static [&'static str];
+
+      /// The class with every field set to its Default, parents included
+      fn build_default() -> Self;
+}
+
+/// Builds a 'T', wrapped by the `<T>Builder` returned by `T::builder()`
+pub struct Builder<T: Build> {
+      value: T,
+}
+
+/// Proof, checked at compile time, that 'T' inherits from 'Part'
+struct Inherits<T, Part>(PhantomData<(T, Part)>);
+
+impl<T: Class, Part: Class> Inherits<T, Part> {
+      const CHECK: () = assert!(isSubclassOf::<T, Part>(), "the built class does not inherit from the class of the setter");
+}
+
+impl<T: Build> Builder<T> {
+      #[allow(clippy::new_without_default)]
+      pub fn new() -> Self {
+            Self {
+                  value: T::build_default()
+            }
+      }
+
+      /// The part of the value being built declared by 'Part', the class itself or one of its parents
+      pub fn part_mut<Part: Class>(&mut self) -> &mut Part {
+            #[allow(clippy::let_unit_value)]
+            let _ = Inherits::<T, Part>::CHECK;
+            let offset = levels(T::info())
+                  .find(|(class, _)| *class == Part::info())
+                  .map(|(_, offset)| offset)
+                  .expect("parts of the class are in its chain");
+            let value = &mut self.value as *mut T as *mut u8;
+            unsafe { &mut *(value.wrapping_add(offset) as *mut Part) }
+      }
+
+      pub fn build(self) -> T {
+            self.value
+      }
+
+      /// Box the value, checking its invariants in debug builds
+      pub fn build_object(self) -> Object<T> where T: 'static {
+            Object::<T>::new(Box::new(self.value))
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase};
+      use super::Build;
+
+      #[subclass(DynamicObjectBase, builder)]
+      struct Widget {
+            name: String,
+            visible: bool,
+      }
+
+      #[subclass(Widget, widget, builder)]
+      struct Button {
+            widget: Widget,
+            label: String,
+      }
+
+      #[subclass(Button, button, builder)]
+      struct Toggle {
+            on: bool,
+            button: Button,
+            group: Option<u32>,
+      }
+
+      #[test]
+      fn every_level() {
+            let toggle = Toggle::builder()
+                  .name("mute".into())
+                  .on(true)
+                  .label("Mute".into())
+                  .visible(true)
+                  .build();
+            assert!(toggle.on && toggle.group.is_none());
+            assert!(toggle.button.label == "Mute");
+            assert!(toggle.button.widget.name == "mute" && toggle.button.widget.visible);
+
+            let object = Button::builder().label("Ok".into()).build_object();
+            assert!(object.label == "Ok" && object.widget.name.is_empty() && !object.widget.visible);
+      }
+
+      mod other {
+            use super::Toggle;
+
+            // Only the struct is imported
+            pub fn toggle() -> Toggle {
+                  Toggle::builder().visible(true).on(true).build()
+            }
+      }
+
+      #[test]
+      fn imports() {
+            let toggle = other::toggle();
+            assert!(toggle.on && toggle.button.widget.visible);
+      }
+
+      #[test]
+      fn setters() {
+            assert!(Widget::SETTERS == ["name", "visible"]);
+            assert!(Toggle::SETTERS == ["on", "group", "label", "name", "visible"]);
+      }
+
+      #[test]
+      fn parts() {
+            let mut builder = Toggle::builder();
+            builder.part_mut::<Widget>().name.push_str("part");
+            builder.part_mut::<Toggle>().group = Some(2);
+            let toggle = builder.build();
+            assert!(toggle.button.widget.name == "part" && toggle.group == Some(2));
+      }
+}
@@ -0,0 +1,186 @@
+//! Map from classes to values, looked up through the inheritance chain
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use dynamic_object::class_map::ClassMap;
+//! #[subclass(DynamicObjectBase)]
+//! struct Light;
+//!
+//! #[subclass(Light, light)]
+//! struct SpotLight {
+//!       light: Light,
+//!       angle: f32
+//! }
+//!
+//! let mut renderers = ClassMap::new();
+//! renderers.insert::<Light>("generic light renderer");
+//!
+//! let spot = Object::<SpotLight>::new(Box::new(SpotLight { light: Light, angle: 30.0 }));
+//! assert!(renderers.get_for(&spot) == Some(&"generic light renderer"));
+//!
+//! renderers.insert::<SpotLight>("spot light renderer");
+//! assert!(renderers.get_for(&spot) == Some(&"spot light renderer"));
+//! ```
+use core::{cell::RefCell, ops::Deref};
+use alloc::collections::BTreeMap;
+
+use crate::{Class, ClassInfo, Object};
+
+/// Values keyed by class id
+///
+/// Lookups for a class without an entry fall back to its nearest ancestor with one
+pub struct ClassMap<V> {
+      entries: BTreeMap<usize, V>,
+      // Dynamic class id -> class id of the entry found for it
+      cache: Option<RefCell<BTreeMap<usize, Option<usize>>>>,
+}
+
+impl<V> ClassMap<V> {
+      pub fn new() -> Self {
+            Self {
+                  entries: BTreeMap::new(),
+                  cache: None,
+            }
+      }
+
+      /// A map remembering the entry found for each dynamic type passed to `get_for`
+      pub fn with_cache() -> Self {
+            Self {
+                  entries: BTreeMap::new(),
+                  cache: Some(RefCell::new(BTreeMap::new())),
+            }
+      }
+
+      /// Set the value of class 'T', returning the previous one
+      pub fn insert<T: Class>(&mut self, value: V) -> Option<V> {
+            self.clearCache();
+            self.entries.insert(T::id(), value)
+      }
+
+      /// Remove the value of class 'T'
+      pub fn remove<T: Class>(&mut self) -> Option<V> {
+            self.clearCache();
+            self.entries.remove(&T::id())
+      }
+
+      /// Value of class 'T' itself
+      pub fn get<T: Class>(&self) -> Option<&V> {
+            self.entries.get(&T::id())
+      }
+
+      /// Value of class 'T', or of its nearest ancestor that has one
+      pub fn get_inherited<T: Class>(&self) -> Option<&V> {
+            self.nearest(T::info()).map(|key| &self.entries[&key])
+      }
+
+      /// Value of the dynamic type of 'object', or of its nearest ancestor that has one
+      pub fn get_for<T: Class, C: Deref>(&self, object: &Object<T, C>) -> Option<&V> {
+            let id = object.dynamic_id();
+            let key = match self.cache {
+                  Some(ref cache) => {
+                        let cached = cache.borrow().get(&id).copied();
+                        match cached {
+                              Some(key) => key,
+                              None => {
+                                    let key = self.nearest(object.class());
+                                    cache.borrow_mut().insert(id, key);
+                                    key
+                              }
+                        }
+                  },
+                  None => self.nearest(object.class()),
+            };
+            key.map(|key| &self.entries[&key])
+      }
+
+      /// Key of the first entry found walking up the parents of 'class'
+      fn nearest(&self, class: &'static ClassInfo) -> Option<usize> {
+            class.ancestors()
+                  .map(ClassInfo::id)
+                  .find(|id| self.entries.contains_key(id))
+      }
+
+      fn clearCache(&mut self) {
+            if let Some(ref mut cache) = self.cache {
+                  cache.get_mut().clear();
+            }
+      }
+
+      pub fn len(&self) -> usize {
+            self.entries.len()
+      }
+
+      pub fn is_empty(&self) -> bool {
+            self.entries.is_empty()
+      }
+
+      /// Values in no particular order
+      pub fn values(&self) -> impl Iterator<Item = &V> {
+            self.entries.values()
+      }
+}
+
+impl<V> Default for ClassMap<V> {
+      fn default() -> Self {
+            Self::new()
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+      use super::ClassMap;
+
+      #[subclass(DynamicObjectBase)]
+      struct Widget;
+
+      #[subclass(Widget, widget)]
+      struct Button {
+            widget: Widget
+      }
+
+      #[subclass(Button, button)]
+      struct Toggle {
+            button: Button,
+            on: bool
+      }
+
+      #[subclass(DynamicObjectBase)]
+      struct Unrelated;
+
+      fn toggle() -> Object<Widget> {
+            Object::<Toggle>::new(Box::new(Toggle { button: Button { widget: Widget }, on: true })).cast::<Widget>()
+      }
+
+      #[test]
+      fn nearest_ancestor() {
+            for mut map in [ClassMap::new(), ClassMap::with_cache()] {
+                  map.insert::<Widget>(1);
+                  assert!(map.get_for(&toggle()) == Some(&1));
+                  map.insert::<Button>(2);
+                  assert!(map.get_for(&toggle()) == Some(&2));
+                  assert!(map.get_inherited::<Toggle>() == Some(&2));
+                  assert!(map.get::<Toggle>().is_none());
+                  map.insert::<Toggle>(3);
+                  assert!(map.get_for(&toggle()) == Some(&3));
+                  map.remove::<Toggle>();
+                  assert!(map.get_for(&toggle()) == Some(&2));
+            }
+      }
+
+      #[test]
+      fn missing() {
+            let mut map = ClassMap::new();
+            map.insert::<Button>("button");
+            let widget = Object::<Widget>::new(Box::new(Widget));
+            let unrelated = Object::<Unrelated>::new(Box::new(Unrelated));
+            assert!(map.get_for(&widget).is_none());
+            assert!(map.get_for(&unrelated).is_none());
+            assert!(map.get_inherited::<Unrelated>().is_none());
+      }
+}
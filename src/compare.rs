@@ -0,0 +1,212 @@
+//! Comparison of objects of any class
+//!
+//! `#[subclass(.., eq, hash, ord)]` registers the class's `PartialEq`, `Hash` and `Ord` implementations
+//! and implements [`ClassEq`], [`ClassHash`] and [`ClassOrd`] for it.
+//! `Object<T>` implements the std traits when 'T' has the matching option, whatever it has been cast to:
+//! - objects of different classes are never equal, and are ordered by the path of their class
+//! - objects of the same class are compared by the implementation of that class,
+//!   or of its nearest parent with the option if the class does not have it
+//!
+//! `Eq` and `Ord` also need 'T' to implement `Eq`.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase, eq, hash, ord)]
+//! #[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+//! struct Class {
+//!       value: u32
+//! }
+//!
+//! #[subclass(Class, parent, eq, hash, ord)]
+//! #[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+//! struct Derived {
+//!       parent: Class,
+//!       field: u32,
+//! }
+//!
+//! let class = |value| Object::<Class>::new(Box::new(Class { value }));
+//! let derived = |field| Object::<Derived>::new(Box::new(Derived { parent: Class { value: 0 }, field })).cast::<Class>();
+//!
+//! let mut objects = vec![derived(2), class(1), derived(1), class(1)];
+//! objects.sort();
+//! objects.dedup();
+//! assert!(objects == [class(1), derived(1), derived(2)]);
+//! ```
+//!
+//! Objects of classes without the option cannot be compared:
+//! ```compile_fail
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! #[derive(PartialEq)]
+//! struct Class {
+//!       value: f32
+//! }
+//!
+//! let class = |value| Object::<Class>::new(Box::new(Class { value }));
+//! assert!(class(1.0) == class(1.0));
+//! ```
+use core::{cmp::Ordering, hash::{Hash, Hasher}, ops::Deref};
+
+use crate::{format::Probe, reflect::levels, Class, ClassInfo, Object};
+
+/// Compares the most-derived structs at two addresses
+pub type EqFn = unsafe fn(object: *const u8, other: *const u8) -> bool;
+/// Hashes the most-derived struct at an address
+pub type HashFn = unsafe fn(object: *const u8, state: &mut dyn Hasher);
+/// Orders the most-derived structs at two addresses
+pub type CmpFn = unsafe fn(object: *const u8, other: *const u8) -> Ordering;
+
+/// Implemented by the `eq` option: the class and its subclasses register a `PartialEq`
+pub trait ClassEq: Class {
+
+}
+
+/// Implemented by the `hash` option: the class and its subclasses register a `Hash`
+pub trait ClassHash: Class {
+
+}
+
+/// Implemented by the `ord` option: the class and its subclasses register an `Ord`
+pub trait ClassOrd: Class {
+
+}
+
+/// The function selected by 'select' of the class nearest to 'class' in its chain, with the offset of that class
+/// panic if no class of the chain has 'option'
+fn nearest<F>(class: &'static ClassInfo, option: &str, select: impl Fn(&ClassInfo) -> Option<F>) -> (F, usize) {
+      match levels(class).find_map(|(level, offset)| Some((select(level)?, offset))) {
+            Some(nearest) => nearest,
+            None => panic!("class {} does not have the {} option", class.path(), option)
+      }
+}
+
+impl<T: ClassEq, C: Deref, OtherT: ClassEq, OtherC: Deref> PartialEq<Object<OtherT, OtherC>> for Object<T, C> {
+      fn eq(&self, other: &Object<OtherT, OtherC>) -> bool {
+            if self.class != other.class {
+                  return false;
+            }
+            let (eq, offset) = nearest(self.class, "eq", |class| class.eq);
+            unsafe { eq(self.base().wrapping_add(offset), other.base().wrapping_add(offset)) }
+      }
+}
+
+impl<T: ClassEq + Eq, C: Deref> Eq for Object<T, C> {
+
+}
+
+impl<T: ClassHash, C: Deref> Hash for Object<T, C> {
+      fn hash<H: Hasher>(&self, state: &mut H) {
+            let (hash, offset) = nearest(self.class, "hash", |class| class.hash);
+            self.class.path().hash(state);
+            unsafe { hash(self.base().wrapping_add(offset), state) }
+      }
+}
+
+impl<T: ClassEq + ClassOrd, C: Deref> PartialOrd for Object<T, C> {
+      fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            if self.class != other.class {
+                  return Some(self.class.path().cmp(other.class.path()));
+            }
+            let (cmp, offset) = nearest(self.class, "ord", |class| class.cmp);
+            Some(unsafe { cmp(self.base().wrapping_add(offset), other.base().wrapping_add(offset)) })
+      }
+}
+
+impl<T: ClassEq + ClassOrd + Eq, C: Deref> Ord for Object<T, C> {
+      fn cmp(&self, other: &Self) -> Ordering {
+            match self.partial_cmp(other) {
+                  Some(ordering) => ordering,
+                  None => unreachable!()
+            }
+      }
+}
+
+/// Compares fields in generated code, see [`Probe`]
+#[doc(hidden)]
+pub trait ProbeEq {
+      unsafe fn equals(&self, a: *const u8, b: *const u8) -> Option<bool>;
+}
+
+impl<T: PartialEq> ProbeEq for Probe<T> {
+      unsafe fn equals(&self, a: *const u8, b: *const u8) -> Option<bool> {
+            Some(*(a as *const T) == *(b as *const T))
+      }
+}
+
+#[doc(hidden)]
+pub trait NoEq {
+      unsafe fn equals(&self, _: *const u8, _: *const u8) -> Option<bool> {
+            None
+      }
+}
+
+impl<T> NoEq for &Probe<T> {
+
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::{collections::HashSet, prelude::rust_2021::*};
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+
+      #[subclass(DynamicObjectBase, eq, hash, ord)]
+      #[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+      struct Base {
+            value: u32
+      }
+
+      #[subclass(Base, parent, eq, hash, ord)]
+      #[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+      struct Derived {
+            parent: Base,
+            field: u32,
+      }
+
+      #[subclass(Base, parent)]
+      struct Plain {
+            parent: Base
+      }
+
+      fn base(value: u32) -> Object<Base> {
+            Object::<Base>::new(Box::new(Base { value }))
+      }
+
+      fn derived(value: u32, field: u32) -> Object<Base> {
+            Object::<Derived>::new(Box::new(Derived { parent: Base { value }, field })).cast()
+      }
+
+      #[test]
+      fn equality_and_hash() {
+            assert!(base(1) == base(1));
+            assert!(base(1) != base(2));
+            // Same parent, different class
+            assert!(base(1) != derived(1, 0));
+            assert!(derived(1, 2) == Object::<Derived>::new(Box::new(Derived { parent: Base { value: 1 }, field: 2 })));
+
+            let set: HashSet<_> = [base(1), base(1), derived(1, 2), derived(1, 2), derived(1, 3)].into_iter().collect();
+            assert!(set.len() == 3);
+      }
+
+      #[test]
+      fn ordering() {
+            let mut objects = vec![derived(0, 2), base(3), derived(5, 1), base(1)];
+            objects.sort();
+            assert!(objects == [base(1), base(3), derived(0, 2), derived(5, 1)]);
+      }
+
+      #[test]
+      fn inherited() {
+            let plain = |value| Object::<Plain>::new(Box::new(Plain { parent: Base { value } })).cast::<Base>();
+            // Compared as their Base
+            assert!(plain(1) == plain(1));
+            assert!(plain(1) != plain(2) && plain(1) != base(1));
+            assert!(plain(1) < plain(2));
+
+            let set: HashSet<_> = [plain(1), plain(1), base(1)].into_iter().collect();
+            assert!(set.len() == 2);
+      }
+}
@@ -0,0 +1,314 @@
+//! Field-level differences between objects
+//!
+//! [`diff`] compares every field of two objects of the same class, inherited ones included,
+//! and lists the changed ones by path, as used by `Object::field_at`.
+//! Only fields whose type converts to and from [`Value`] are recorded as changes. The others are listed
+//! by [`ObjectDiff::skipped`] when they differ, or when their type does not implement `PartialEq` either,
+//! and [`apply`] refuses a diff that skipped any.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! #[subclass(Class, parent)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let derived = |field, value| Object::<Derived>::new(Box::new(Derived { field, parent: Class { value, foo: 0 } })).cast::<Class>();
+//! let (before, after) = (derived(1, 2), derived(1, 3));
+//!
+//! let changes = diff::diff(&before, &after);
+//! assert!(changes.changes()[0].path == "parent.value");
+//!
+//! let mut object = derived(1, 2);
+//! diff::apply(&mut object, &changes).unwrap();
+//! assert!(object.value == 3);
+//! diff::apply(&mut object, &changes.reverse()).unwrap();
+//! assert!(object.value == 2);
+//! ```
+use core::{fmt, ops::{Deref, DerefMut}};
+use alloc::{string::String, vec::Vec};
+
+use crate::{reflect::{levels, FieldError}, value::Value, Class, ClassInfo, Object};
+
+/// A changed field
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+      /// Path of the field from the class of the objects
+      pub path: String,
+      pub old: Value,
+      pub new: Value,
+}
+
+/// The fields that differ between two objects of a class, returned by [`diff`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectDiff {
+      class: &'static ClassInfo,
+      changes: Vec<Change>,
+      skipped: Vec<String>,
+}
+
+impl ObjectDiff {
+      /// The class of the objects compared
+      pub fn class(&self) -> &'static ClassInfo {
+            self.class
+      }
+
+      pub fn changes(&self) -> &[Change] {
+            &self.changes
+      }
+
+      pub fn is_empty(&self) -> bool {
+            self.changes.is_empty()
+      }
+
+      /// Paths of the fields that differ, or may differ, but whose type does not convert to and from [`Value`]
+      pub fn skipped(&self) -> &[String] {
+            &self.skipped
+      }
+
+      /// The diff going from the new values back to the old ones
+      pub fn reverse(&self) -> Self {
+            Self {
+                  class: self.class,
+                  changes: self.changes.iter().map(|change| Change {
+                        path: change.path.clone(),
+                        old: change.new.clone(),
+                        new: change.old.clone(),
+                  }).collect(),
+                  skipped: self.skipped.clone(),
+            }
+      }
+}
+
+/// Compare the fields of 'old' and 'new'
+/// panic if they were not created as the same class
+pub fn diff<T: Class, C: Deref, OtherC: Deref>(old: &Object<T, C>, new: &Object<T, OtherC>) -> ObjectDiff {
+      match try_diff(old, new) {
+            Some(diff) => diff,
+            None => panic!("cannot diff a {} with a {}", old.class.path(), new.class.path())
+      }
+}
+
+/// Compare the fields of 'old' and 'new', None if they were not created as the same class
+pub fn try_diff<T: Class, C: Deref, OtherC: Deref>(old: &Object<T, C>, new: &Object<T, OtherC>) -> Option<ObjectDiff> {
+      if old.class != new.class {
+            return None
+      }
+      let mut changes = Vec::new();
+      let mut skipped = Vec::new();
+      let mut prefix = String::new();
+      for (class, offset) in levels(old.class) {
+            let mut parentField = None;
+            for field in class.fields {
+                  if field.parent.is_some() {
+                        parentField = Some(field.name);
+                        continue;
+                  }
+                  let offset = offset + field.offset;
+                  let (oldField, newField) = (old.base().wrapping_add(offset), new.base().wrapping_add(offset));
+                  let values = unsafe { (field.to_value)(oldField).zip((field.to_value)(newField)) };
+                  match values {
+                        Some((oldValue, newValue)) if oldValue != newValue => changes.push(Change {
+                              path: prefix.clone() + field.name,
+                              old: oldValue,
+                              new: newValue,
+                        }),
+                        Some(_) => {},
+                        // Unchanged fields need no conversion
+                        None if unsafe { (field.equals)(oldField, newField) } == Some(true) => {},
+                        None => skipped.push(prefix.clone() + field.name),
+                  }
+            }
+            // Fields of classes without a parent field cannot be reached by path
+            match parentField {
+                  Some(name) => {
+                        prefix.push_str(name);
+                        prefix.push('.');
+                  },
+                  None => break,
+            }
+      }
+      Some(ObjectDiff {
+            class: old.class,
+            changes,
+            skipped,
+      })
+}
+
+/// Why [`apply`] left the object unchanged
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApplyError {
+      /// The diff was made between objects of another class
+      WrongClass { expected: &'static ClassInfo, found: &'static ClassInfo },
+      /// The diff skipped these fields, applying it would only carry over part of the state
+      Incomplete(Vec<String>),
+      /// A change cannot be written to the object
+      Field { path: String, error: FieldError },
+}
+
+impl fmt::Display for ApplyError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  ApplyError::WrongClass { expected, found } => write!(f, "diff of a {} applied to a {}", expected.path(), found.path()),
+                  ApplyError::Incomplete(paths) => write!(f, "fields {} were not compared", paths.join(", ")),
+                  ApplyError::Field { path, error } => write!(f, "{}: {}", path, error),
+            }
+      }
+}
+
+/// Set the fields changed by 'diff' to their new value
+/// Every change is checked before any field is written, so on error the object is left unchanged
+pub fn apply<T: Class, C: DerefMut>(object: &mut Object<T, C>, diff: &ObjectDiff) -> Result<(), ApplyError> {
+      if diff.class != object.class {
+            return Err(ApplyError::WrongClass {
+                  expected: diff.class,
+                  found: object.class,
+            })
+      }
+      if !diff.skipped.is_empty() {
+            return Err(ApplyError::Incomplete(diff.skipped.clone()))
+      }
+      let mut writes = Vec::with_capacity(diff.changes.len());
+      for change in &diff.changes {
+            let error = |error| ApplyError::Field {
+                  path: change.path.clone(),
+                  error,
+            };
+            let field = object.field_at(&change.path).map_err(error)?;
+            if !(field.info().accepts_value)(&change.new) {
+                  return Err(error(FieldError::WrongType))
+            }
+            writes.push((field, &change.new));
+      }
+      for (field, value) in writes {
+            let address = object.baseMut().wrapping_add(field.offset());
+            let written = unsafe { (field.info().set_value)(address, value) };
+            debug_assert!(written);
+      }
+      Ok(())
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase)]
+      struct Entity {
+            id: u64,
+            name: String,
+      }
+
+      #[subclass(Entity, entity)]
+      struct Player {
+            entity: Entity,
+            score: u32,
+            // Not convertible to a Value
+            tags: Vec<&'static str>,
+      }
+
+      #[subclass(Entity, entity)]
+      struct Monster {
+            entity: Entity,
+            health: u32,
+      }
+
+      fn player(name: &str, score: u32, tags: Vec<&'static str>) -> Object<Entity> {
+            Object::<Player>::new(Box::new(Player { entity: Entity { id: 1, name: name.into() }, score, tags })).cast()
+      }
+
+      fn monster(name: &str, health: u32) -> Object<Entity> {
+            Object::<Monster>::new(Box::new(Monster { entity: Entity { id: 2, name: name.into() }, health })).cast()
+      }
+
+      #[test]
+      fn changes() {
+            let old = player("a", 1, vec![]);
+            let new = player("b", 2, vec!["ignored"]);
+            let changes = diff(&old, &new);
+            assert!(changes.class().name() == "Player");
+            assert!(changes.changes() == [
+                  Change { path: "score".into(), old: Value::U32(1), new: Value::U32(2) },
+                  Change { path: "entity.name".into(), old: "a".into(), new: "b".into() },
+            ]);
+            assert!(changes.skipped() == ["tags"]);
+            assert!(diff(&old, &player("a", 1, vec![])).is_empty());
+            let same = diff(&player("a", 1, vec!["same"]), &player("a", 1, vec!["same"]));
+            assert!(same.is_empty() && same.skipped().is_empty());
+            assert!(try_diff(&old, &monster("a", 1)).is_none());
+
+            // Not applied at all rather than without the tags
+            let mut object = player("a", 1, vec![]);
+            assert!(apply(&mut object, &changes) == Err(ApplyError::Incomplete(vec!["tags".into()])));
+            assert!(object.name == "a");
+      }
+
+      #[test]
+      fn apply_and_reverse() {
+            let old = monster("a", 1);
+            let new = monster("b", 2);
+            let changes = diff(&old, &new);
+            assert!(changes.skipped().is_empty());
+
+            let mut object = monster("a", 1);
+            apply(&mut object, &changes).unwrap();
+            assert!(diff(&object, &new).is_empty());
+            apply(&mut object, &changes.reverse()).unwrap();
+            assert!(diff(&object, &old).is_empty());
+
+            let mut player = player("a", 1, vec![]);
+            assert!(apply(&mut player, &changes) == Err(ApplyError::WrongClass { expected: changes.class(), found: player.class() }));
+      }
+
+      #[subclass(Entity, entity)]
+      struct Inventory {
+            entity: Entity,
+            items: Vec<u32>,
+            gold: u32,
+      }
+
+      #[test]
+      fn unchanged_unconvertible() {
+            let inventory = |gold| Object::<Inventory>::new(Box::new(Inventory { entity: Entity { id: 3, name: "bag".into() }, items: vec![1, 2], gold }));
+            let changes = diff(&inventory(1), &inventory(5));
+            assert!(changes.skipped().is_empty());
+
+            let mut object = inventory(1);
+            apply(&mut object, &changes).unwrap();
+            assert!(object.gold == 5 && object.items == [1, 2]);
+      }
+
+      #[test]
+      fn all_or_nothing() {
+            let mut object = monster("a", 1);
+            let class = object.class();
+            let change = |path: &str, new: Value| Change { path: path.into(), old: Value::Unit, new };
+
+            let missing = ObjectDiff {
+                  class,
+                  changes: vec![change("health", Value::U32(5)), change("entity.level", Value::U32(2))],
+                  skipped: vec![],
+            };
+            assert!(apply(&mut object, &missing) == Err(ApplyError::Field { path: "entity.level".into(), error: FieldError::NotFound }));
+
+            let wrong = ObjectDiff {
+                  class,
+                  changes: vec![change("health", Value::U32(5)), change("entity.name", Value::I8(2))],
+                  skipped: vec![],
+            };
+            assert!(apply(&mut object, &wrong) == Err(ApplyError::Field { path: "entity.name".into(), error: FieldError::WrongType }));
+            assert!(object.cast_ref::<Monster>().health == 1);
+      }
+}
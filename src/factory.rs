@@ -0,0 +1,162 @@
+//! Create objects from the name of their class
+//!
+//! Classes opt in with `#[constructible]`, which builds them with `Default`,
+//! or `#[constructible(constructor)]`, which calls `constructor(&Args) -> Self`.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use dynamic_object::factory::{Factory, FactoryError};
+//! #[subclass(DynamicObjectBase)]
+//! #[constructible]
+//! #[derive(Default)]
+//! struct Light {
+//!       intensity: f32
+//! }
+//!
+//! #[subclass(Light, light)]
+//! #[constructible(SpotLight::from_angle)]
+//! struct SpotLight {
+//!       light: Light,
+//!       angle: f32
+//! }
+//!
+//! impl SpotLight {
+//!       fn from_angle(angle: &f32) -> Self {
+//!             SpotLight { light: Light { intensity: 1.0 }, angle: *angle }
+//!       }
+//! }
+//!
+//! let light = Factory::create::<Light>("SpotLight", &45.0f32).unwrap();
+//! assert!(light.intensity == 1.0);
+//! assert!(light.cast::<SpotLight>().angle == 45.0);
+//! assert!(Factory::create::<SpotLight>("Light", &()).err() == Some(FactoryError::NotSubclass));
+//! ```
+use core::{any::Any, fmt};
+use alloc::vec::Vec;
+
+use crate::{invariant::InvariantError, registry::{self, LookupError}, Class, ClassInfo, Object};
+
+/// Why an object could not be created
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FactoryError {
+      /// No class has this name
+      UnknownClass,
+      /// Several classes have this name, the paths of which are listed
+      Ambiguous(Vec<&'static str>),
+      /// The class is not #[constructible]
+      NotConstructible,
+      /// The class does not inherit from the requested base class
+      NotSubclass,
+      /// The arguments are not of the type the constructor takes
+      InvalidArguments,
+      /// The object created breaks an invariant of its class
+      Invariant(InvariantError),
+}
+
+impl fmt::Display for FactoryError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  FactoryError::UnknownClass => write!(f, "unknown class"),
+                  FactoryError::Ambiguous(paths) => write!(f, "ambiguous class name, use one of the paths {}", paths.join(", ")),
+                  FactoryError::NotConstructible => write!(f, "class is not constructible"),
+                  FactoryError::NotSubclass => write!(f, "class does not inherit from the requested base class"),
+                  FactoryError::InvalidArguments => write!(f, "invalid constructor arguments"),
+                  FactoryError::Invariant(err) => write!(f, "{}", err),
+            }
+      }
+}
+
+/// Creates objects of #[constructible] classes by name
+pub struct Factory;
+
+impl Factory {
+      /// Create an object of the class with path 'name', or the only one called 'name', and cast it to 'Base'
+      pub fn create<Base: Class>(name: &str, args: &dyn Any) -> Result<Object<Base>, FactoryError> {
+            let class = registry::by_name(name).map_err(|err| match err {
+                  LookupError::UnknownClass => FactoryError::UnknownClass,
+                  LookupError::Ambiguous(paths) => FactoryError::Ambiguous(paths),
+            })?;
+            Self::create_class(class, args)
+      }
+
+      /// Create an object of 'class' and cast it to 'Base'
+      pub fn create_class<Base: Class>(class: &ClassInfo, args: &dyn Any) -> Result<Object<Base>, FactoryError> {
+            if !class.is_subclass_of(Base::info()) {
+                  return Err(FactoryError::NotSubclass);
+            }
+            let construct = class.construct.ok_or(FactoryError::NotConstructible)?;
+            let object = construct(args).ok_or(FactoryError::InvalidArguments)?;
+            object.check_invariants().map_err(FactoryError::Invariant)?;
+            Ok(object.cast())
+      }
+
+      /// Every constructible class inheriting from 'Base', 'Base' included
+      pub fn classes<Base: Class>() -> impl Iterator<Item = &'static ClassInfo> {
+            registry::all().filter(|class| class.construct.is_some() && class.is_subclass_of(Base::info()))
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::{constructible, subclass};
+      use crate::{self as dynamic_object, DynamicObjectBase};
+      use super::*;
+
+      struct Config {
+            size: u32
+      }
+
+      #[subclass(DynamicObjectBase)]
+      #[constructible]
+      #[derive(Default)]
+      struct Component {
+            enabled: bool
+      }
+
+      #[constructible(Collider::new)]
+      #[subclass(Component, component)]
+      struct Collider {
+            size: u32,
+            component: Component
+      }
+
+      impl Collider {
+            fn new(config: &Config) -> Self {
+                  Collider {
+                        size: config.size,
+                        component: Component { enabled: true }
+                  }
+            }
+      }
+
+      #[subclass(Component, component)]
+      struct Script {
+            component: Component
+      }
+
+      #[test]
+      fn create() {
+            let component = Factory::create::<Component>("Collider", &Config { size: 3 }).unwrap();
+            assert!(component.enabled);
+            assert!(component.cast::<Collider>().size == 3);
+
+            let component = Factory::create::<Component>("Component", &()).unwrap();
+            assert!(!component.enabled);
+
+            let mut classes: Vec<_> = Factory::classes::<Component>().map(ClassInfo::name).collect();
+            classes.sort();
+            assert!(classes == ["Collider", "Component"]);
+      }
+
+      #[test]
+      fn errors() {
+            assert!(Factory::create::<Component>("Nothing", &()).err() == Some(FactoryError::UnknownClass));
+            assert!(Factory::create::<Component>("Script", &()).err() == Some(FactoryError::NotConstructible));
+            assert!(Factory::create::<Collider>("Component", &()).err() == Some(FactoryError::NotSubclass));
+            assert!(Factory::create::<Component>("Collider", &5u32).err() == Some(FactoryError::InvalidArguments));
+      }
+}
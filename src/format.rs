@@ -0,0 +1,189 @@
+//! Debug and Display of objects of any class
+//!
+//! `#[subclass]` records the `Debug` and `Display` implementations of the class, if it has any.
+//! [`Object`] prints with the class it was created as, whatever it has been cast to since:
+//! - `Debug` prints the object followed by the class it is seen as, `Name { .. }` if the class has no `Debug`
+//! - `Display`, for objects seen as a class implementing it, uses the first `Display` found from the class
+//!   the object was created as up to the class it is seen as
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! impl core::fmt::Debug for Class {
+//!       fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+//!             f.debug_struct("Class").finish_non_exhaustive()
+//!       }
+//! }
+//!
+//! #[subclass(Class, parent)]
+//! #[derive(Debug)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let object = Object::<Derived>::new(Box::new(Derived { field: 2153746, parent: Class { value: 1, foo: 2 } }));
+//! let object = object.cast::<Class>();
+//! assert!(format!("{:?}", object) == "Derived { field: 2153746, parent: Class { .. } } as Class");
+//! ```
+use core::{fmt, marker::PhantomData, ops::Deref};
+
+use crate::{reflect::levels, Class, Object};
+
+/// Formats the most-derived struct at an address, None if the class does not implement the trait
+pub type FormatFn = unsafe fn(object: *const u8, f: &mut fmt::Formatter<'_>) -> Option<fmt::Result>;
+
+/// Finds out in generated code which traits 'T' implements
+///
+/// `(&Probe::<T>::new()).debug(..)` resolves to [`ProbeDebug`] when `T: Debug`, to [`NoDebug`] otherwise
+#[doc(hidden)]
+pub struct Probe<T>(PhantomData<T>);
+
+impl<T> Probe<T> {
+      #[allow(clippy::new_without_default)]
+      pub fn new() -> Self {
+            Self(PhantomData)
+      }
+}
+
+#[doc(hidden)]
+pub trait ProbeDebug {
+      unsafe fn debug(&self, object: *const u8, f: &mut fmt::Formatter<'_>) -> Option<fmt::Result>;
+}
+
+impl<T: fmt::Debug> ProbeDebug for Probe<T> {
+      unsafe fn debug(&self, object: *const u8, f: &mut fmt::Formatter<'_>) -> Option<fmt::Result> {
+            Some(fmt::Debug::fmt(&*(object as *const T), f))
+      }
+}
+
+#[doc(hidden)]
+pub trait NoDebug {
+      unsafe fn debug(&self, _: *const u8, _: &mut fmt::Formatter<'_>) -> Option<fmt::Result> {
+            None
+      }
+}
+
+impl<T> NoDebug for &Probe<T> {
+
+}
+
+#[doc(hidden)]
+pub trait ProbeDisplay {
+      unsafe fn display(&self, object: *const u8, f: &mut fmt::Formatter<'_>) -> Option<fmt::Result>;
+}
+
+impl<T: fmt::Display> ProbeDisplay for Probe<T> {
+      unsafe fn display(&self, object: *const u8, f: &mut fmt::Formatter<'_>) -> Option<fmt::Result> {
+            Some(fmt::Display::fmt(&*(object as *const T), f))
+      }
+}
+
+#[doc(hidden)]
+pub trait NoDisplay {
+      unsafe fn display(&self, _: *const u8, _: &mut fmt::Formatter<'_>) -> Option<fmt::Result> {
+            None
+      }
+}
+
+impl<T> NoDisplay for &Probe<T> {
+
+}
+
+impl<T: Class, C: Deref> fmt::Debug for Object<T, C> {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match unsafe { (self.class.debug)(self.base(), f) } {
+                  Some(result) => result?,
+                  None => f.debug_struct(self.class.name()).finish_non_exhaustive()?,
+            }
+            let seenAs = T::info();
+            if seenAs != self.class {
+                  write!(f, " as {}", seenAs.name())?;
+            }
+            Ok(())
+      }
+}
+
+impl<T: Class + fmt::Display, C: Deref> fmt::Display for Object<T, C> {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for (class, offset) in levels(self.class) {
+                  let object = self.base().wrapping_add(offset);
+                  if let Some(result) = unsafe { (class.display)(object, f) } {
+                        return result;
+                  }
+            }
+            // Not reached, 'T' is in the chain
+            fmt::Display::fmt(&**self, f)
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::{format, prelude::rust_2021::*};
+      use core::fmt;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+
+      #[subclass(DynamicObjectBase)]
+      #[derive(Debug)]
+      struct Shape {
+            name: &'static str
+      }
+
+      impl fmt::Display for Shape {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                  write!(f, "shape {}", self.name)
+            }
+      }
+
+      #[subclass(Shape, parent)]
+      #[derive(Debug)]
+      struct Circle {
+            parent: Shape,
+            radius: u32,
+      }
+
+      #[subclass(Circle, parent)]
+      struct Ring {
+            parent: Circle,
+            width: u32,
+      }
+
+      impl fmt::Display for Ring {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                  write!(f, "ring {} of {}", self.width, self.parent.radius)
+            }
+      }
+
+      fn circle() -> Circle {
+            Circle { parent: Shape { name: "c" }, radius: 2 }
+      }
+
+      #[test]
+      fn debug() {
+            let object = Object::<Circle>::new(Box::new(circle()));
+            assert!(format!("{:?}", object) == r#"Circle { parent: Shape { name: "c" }, radius: 2 }"#);
+            let object = object.cast::<Shape>();
+            assert!(format!("{:?}", object) == r#"Circle { parent: Shape { name: "c" }, radius: 2 } as Shape"#);
+
+            let ring = Object::<Ring>::new(Box::new(Ring { parent: circle(), width: 1 })).cast::<DynamicObjectBase>();
+            assert!(format!("{:?}", ring) == "Ring { .. } as DynamicObjectBase");
+      }
+
+      #[test]
+      fn display() {
+            let ring = Object::<Ring>::new(Box::new(Ring { parent: circle(), width: 1 })).cast::<Shape>();
+            assert!(format!("{}", ring) == "ring 1 of 2");
+            // Circle has no Display, Shape's is used
+            let circle = Object::<Circle>::new(Box::new(circle())).cast::<Shape>();
+            assert!(format!("{}", circle) == "shape c");
+      }
+}
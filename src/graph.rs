@@ -0,0 +1,431 @@
+//! Serialization of object graphs, enabled by the `graph` feature
+//!
+//! The ids of a scope are kept in a thread local, so the feature also enables `std`.
+//!
+//! [`Ref`] is a shared, reference counted object and [`WeakRef`] its non-owning counterpart.
+//! The first time a `Ref` to an object is written it gets an instance id and is written as
+//! `{ "id": n, "class": name, "data": value }` (with "versions" for versioned classes), every other reference to it as `{ "ref": n }`,
+//! so loading restores aliasing and cycles. Classes opt in like with [`serialization`](crate::serialization),
+//! and like there no fingerprint of the classes is checked.
+//!
+//! References share ids while a [`Scope`] is being written or read,
+//! otherwise each top-level `Ref` gets its own ids.
+//! Reading a cycle needs "id" to come before "data", as written.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use dynamic_object::graph::{Ref, Scope, WeakRef};
+//! # use serde::{Serialize, Deserialize};
+//! #[subclass(DynamicObjectBase, serde_name = "node")]
+//! #[derive(Serialize, Deserialize)]
+//! struct Node {
+//!       parent: WeakRef<Node>,
+//!       children: Vec<Ref<Node>>
+//! }
+//!
+//! let root = Ref::new_cyclic(|root| Object::new(Box::new(Node {
+//!       parent: WeakRef::new(),
+//!       children: vec![Ref::new(Object::new(Box::new(Node { parent: root.clone(), children: vec![] })))]
+//! })));
+//! let json = serde_json::to_string(&Scope(&root)).unwrap();
+//!
+//! let Scope(loaded): Scope<Ref<Node>> = serde_json::from_str(&json).unwrap();
+//! let child = &loaded.children[0];
+//! assert!(Ref::ptr_eq(&child.parent.upgrade().unwrap(), &loaded));
+//! ```
+use core::{cell::{OnceCell, RefCell}, fmt, marker::PhantomData, ops::Deref};
+use alloc::{collections::BTreeMap, rc::{Rc, Weak}, string::String};
+use serde::{de, ser::{self, SerializeStruct}, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{serialization, Class, ClassInfo, DynamicObjectBase, Object};
+
+/// Holds an object, empty while it is being read
+#[derive(Default)]
+struct Slot(OnceCell<Object<DynamicObjectBase>>);
+
+/// Instance ids of the scope being written or read
+#[derive(Default)]
+struct Context {
+      // Address of a slot -> id
+      written: BTreeMap<usize, u64>,
+      read: BTreeMap<u64, Rc<Slot>>,
+}
+
+std::thread_local! {
+      static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+/// Opens a scope if none is open, closing it when dropped
+struct Guard {
+      owner: bool,
+}
+
+impl Guard {
+      fn enter() -> Self {
+            CONTEXT.with(|context| {
+                  let mut context = context.borrow_mut();
+                  let owner = context.is_none();
+                  if owner {
+                        *context = Some(Context::default());
+                  }
+                  Guard { owner }
+            })
+      }
+}
+
+impl Drop for Guard {
+      fn drop(&mut self) {
+            if self.owner {
+                  // Take it out first so the slots are dropped once the context is released
+                  let context = CONTEXT.with(|context| context.borrow_mut().take());
+                  drop(context);
+            }
+      }
+}
+
+fn context<R>(f: impl FnOnce(&mut Context) -> R) -> R {
+      CONTEXT.with(|context| f(context.borrow_mut().as_mut().expect("no graph scope is open")))
+}
+
+/// Writes or reads 'T' with instance ids shared by every [`Ref`] inside it
+pub struct Scope<T>(pub T);
+
+impl<T: Serialize> Serialize for Scope<T> {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let _guard = Guard::enter();
+            self.0.serialize(serializer)
+      }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Scope<T> {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let _guard = Guard::enter();
+            T::deserialize(deserializer).map(Scope)
+      }
+}
+
+/// A shared object that keeps its identity when serialized
+pub struct Ref<T: Class> {
+      slot: Rc<Slot>,
+      _marker: PhantomData<T>,
+}
+
+impl<T: Class> Ref<T> {
+      pub fn new(object: Object<T>) -> Self {
+            Self {
+                  slot: Rc::new(Slot(OnceCell::from(object.cast()))),
+                  _marker: PhantomData,
+            }
+      }
+
+      /// Create an object holding weak references to itself
+      pub fn new_cyclic(f: impl FnOnce(&WeakRef<T>) -> Object<T>) -> Self {
+            let slot = Rc::new_cyclic(|weak| {
+                  let weak = WeakRef {
+                        slot: weak.clone(),
+                        _marker: PhantomData,
+                  };
+                  Slot(OnceCell::from(f(&weak).cast()))
+            });
+            Self {
+                  slot,
+                  _marker: PhantomData,
+            }
+      }
+
+      /// None while the object is being read, when reached through a cycle
+      pub fn try_get(&self) -> Option<&T> {
+            self.slot.0.get().map(|object| object.cast_ref())
+      }
+
+      /// Mutably borrow the object if no other Ref or WeakRef points to it
+      pub fn get_mut(&mut self) -> Option<&mut T> {
+            Rc::get_mut(&mut self.slot)?.0.get_mut().map(|object| object.cast_mut())
+      }
+
+      /// The class the object was created as
+      pub fn class(&self) -> &'static ClassInfo {
+            self.object().class()
+      }
+
+      pub fn downgrade(&self) -> WeakRef<T> {
+            WeakRef {
+                  slot: Rc::downgrade(&self.slot),
+                  _marker: PhantomData,
+            }
+      }
+
+      /// Check if both point to the same object
+      pub fn ptr_eq<U: Class>(this: &Self, other: &Ref<U>) -> bool {
+            Rc::ptr_eq(&this.slot, &other.slot)
+      }
+
+      /// Try to cast to 'Cast'
+      pub fn try_cast<Cast: Class>(self) -> Result<Ref<Cast>, Self> {
+            if !self.object().isa::<Cast>() {
+                  return Err(self);
+            }
+            Ok(Ref {
+                  slot: self.slot,
+                  _marker: PhantomData,
+            })
+      }
+
+      fn object(&self) -> &Object<DynamicObjectBase> {
+            self.slot.0.get().expect("object is still being read")
+      }
+}
+
+impl<T: Class> Deref for Ref<T> {
+      type Target = T;
+
+      fn deref(&self) -> &T {
+            self.object().cast_ref()
+      }
+}
+
+impl<T: Class> Clone for Ref<T> {
+      fn clone(&self) -> Self {
+            Self {
+                  slot: self.slot.clone(),
+                  _marker: PhantomData,
+            }
+      }
+}
+
+/// A non-owning reference to a [`Ref`] object
+pub struct WeakRef<T: Class> {
+      slot: Weak<Slot>,
+      _marker: PhantomData<T>,
+}
+
+impl<T: Class> WeakRef<T> {
+      /// A reference to nothing
+      pub fn new() -> Self {
+            Self {
+                  slot: Weak::new(),
+                  _marker: PhantomData,
+            }
+      }
+
+      pub fn upgrade(&self) -> Option<Ref<T>> {
+            Some(Ref {
+                  slot: self.slot.upgrade()?,
+                  _marker: PhantomData,
+            })
+      }
+}
+
+impl<T: Class> Default for WeakRef<T> {
+      fn default() -> Self {
+            Self::new()
+      }
+}
+
+impl<T: Class> Clone for WeakRef<T> {
+      fn clone(&self) -> Self {
+            Self {
+                  slot: self.slot.clone(),
+                  _marker: PhantomData,
+            }
+      }
+}
+
+impl<T: Class> Serialize for Ref<T> {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let _guard = Guard::enter();
+            let object = self.slot.0.get().ok_or_else(|| ser::Error::custom("object is still being read"))?;
+            let key = Rc::as_ptr(&self.slot) as usize;
+            let (id, written) = context(|context| {
+                  let count = context.written.len() as u64;
+                  match context.written.get(&key) {
+                        Some(&id) => (id, true),
+                        None => {
+                              context.written.insert(key, count);
+                              (count, false)
+                        }
+                  }
+            });
+            if written {
+                  let mut node = serializer.serialize_struct("Ref", 1)?;
+                  node.serialize_field("ref", &id)?;
+                  return node.end();
+            }
+
+            let class = serialization::of(object.class()).ok_or_else(|| {
+                  ser::Error::custom(format_args!("class {} is not serializable", object.class().path()))
+            })?;
+            let data = unsafe { &*(class.serialize)(object.base()) };
+            let versions = serialization::Versions::of(object.class());
+            let mut node = serializer.serialize_struct("Ref", if versions.is_empty() { 3 } else { 4 })?;
+            node.serialize_field("id", &id)?;
+            node.serialize_field("class", class.name())?;
+            if versions.is_empty() {
+                  node.skip_field("versions")?;
+            } else {
+                  node.serialize_field("versions", &versions)?;
+            }
+            node.serialize_field("data", data)?;
+            node.end()
+      }
+}
+
+impl<T: Class> Serialize for WeakRef<T> {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.upgrade().serialize(serializer)
+      }
+}
+
+struct RefVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Class> de::Visitor<'de> for RefVisitor<T> {
+      type Value = Ref<T>;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an object with an id, a class and data, or a reference to one")
+      }
+
+      fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut slot: Option<Rc<Slot>> = None;
+            let mut class = None;
+            let mut versions = None;
+            let mut loaded = None;
+            while let Some(key) = map.next_key::<String>()? {
+                  match key.as_str() {
+                        "ref" => {
+                              let id: u64 = map.next_value()?;
+                              let found = context(|context| context.read.get(&id).cloned());
+                              slot = Some(found.ok_or_else(|| de::Error::custom(format_args!("reference to unknown object {}", id)))?);
+                        },
+                        // Registered before reading the data so references from inside it resolve
+                        "id" => {
+                              let id: u64 = map.next_value()?;
+                              let created = slot.get_or_insert_with(Default::default).clone();
+                              context(|context| context.read.insert(id, created));
+                        },
+                        "class" => {
+                              let name: String = map.next_value()?;
+                              class = Some(serialization::class(&name)?);
+                        },
+                        "versions" => versions = Some(map.next_value::<serialization::SavedVersions>()?),
+                        "data" => match class {
+                              Some(class) => loaded = Some(map.next_value_seed(serialization::Data { class, versions: versions.as_ref() })?),
+                              None => return Err(de::Error::custom("\"class\" must come before \"data\"")),
+                        },
+                        _ => {
+                              map.next_value::<de::IgnoredAny>()?;
+                        },
+                  }
+            }
+
+            let slot = match class.zip(loaded) {
+                  Some((class, loaded)) => {
+                        let object = loaded.finish(class, &versions.unwrap_or_default())?;
+                        if !object.isa::<T>() {
+                              return Err(de::Error::custom(format_args!("class {} does not inherit from {}", object.class().path(), T::info().path())));
+                        }
+                        let slot = slot.unwrap_or_default();
+                        let _ = slot.0.set(object);
+                        slot
+                  },
+                  None => slot.ok_or_else(|| de::Error::missing_field("ref"))?,
+            };
+            Ok(Ref {
+                  slot,
+                  _marker: PhantomData,
+            })
+      }
+}
+
+impl<'de, T: Class> Deserialize<'de> for Ref<T> {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let _guard = Guard::enter();
+            deserializer.deserialize_struct("Ref", &["id", "ref", "class", "versions", "data"], RefVisitor(PhantomData))
+      }
+}
+
+impl<'de, T: Class> Deserialize<'de> for WeakRef<T> {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let object: Option<Ref<T>> = Deserialize::deserialize(deserializer)?;
+            Ok(object.map_or_else(WeakRef::new, |object| object.downgrade()))
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use serde::{Serialize, Deserialize};
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase, serde)]
+      #[derive(Serialize, Deserialize)]
+      struct Entity {
+            name: String,
+            parent: WeakRef<Entity>,
+            children: Vec<Ref<Entity>>
+      }
+
+      #[subclass(Entity, entity, serde)]
+      #[derive(Serialize, Deserialize)]
+      struct Mesh {
+            entity: Entity,
+            material: Ref<Material>
+      }
+
+      #[subclass(DynamicObjectBase, serde)]
+      #[derive(Serialize, Deserialize)]
+      struct Material {
+            color: u32
+      }
+
+      fn entity(name: &str, parent: &WeakRef<Entity>, children: Vec<Ref<Entity>>) -> Entity {
+            Entity { name: name.into(), parent: parent.clone(), children }
+      }
+
+      fn mesh(name: &str, parent: &WeakRef<Entity>, material: &Ref<Material>) -> Ref<Entity> {
+            let mesh = Mesh { entity: entity(name, parent, vec![]), material: material.clone() };
+            Ref::new(Object::<Mesh>::new(Box::new(mesh)).cast())
+      }
+
+      #[test]
+      fn aliasing_and_cycles() {
+            let material = Ref::new(Object::<Material>::new(Box::new(Material { color: 0xff0000 })));
+            let scene = Ref::new_cyclic(|scene| Object::new(Box::new(entity("scene", &WeakRef::new(), vec![
+                  mesh("a", scene, &material),
+                  mesh("b", scene, &material),
+            ]))));
+            let json = serde_json::to_string(&Scope(&scene)).unwrap();
+            // The material is written once
+            assert!(json.matches("16711680").count() == 1);
+
+            let Scope(scene): Scope<Ref<Entity>> = serde_json::from_str(&json).unwrap();
+            assert!(scene.parent.upgrade().is_none());
+            let (a, b) = (&scene.children[0], &scene.children[1]);
+            assert!(a.name == "a" && b.class() == Mesh::info());
+            assert!(Ref::ptr_eq(&a.parent.upgrade().unwrap(), &scene));
+            let a = a.clone().try_cast::<Mesh>().ok().unwrap();
+            let b = b.clone().try_cast::<Mesh>().ok().unwrap();
+            assert!(Ref::ptr_eq(&a.material, &b.material));
+            assert!(a.material.color == 0xff0000);
+      }
+
+      #[test]
+      fn scopes() {
+            let material = Ref::new(Object::<Material>::new(Box::new(Material { color: 1 })));
+            let pair = (material.clone(), material.clone());
+            let shared = serde_json::to_value(Scope(&pair)).unwrap();
+            assert!(shared[1]["ref"] == 0);
+            let separate = serde_json::to_value(&pair).unwrap();
+            assert!(separate[1]["class"] == Material::info().path());
+
+            let Scope((first, second)): Scope<(Ref<Material>, Ref<Material>)> = serde_json::from_value(shared).unwrap();
+            assert!(Ref::ptr_eq(&first, &second));
+            assert!(serde_json::from_str::<Ref<Material>>(r#"{"ref":3}"#).is_err());
+            assert!(serde_json::from_value::<Ref<Entity>>(serde_json::to_value(&material).unwrap()).is_err());
+      }
+}
@@ -0,0 +1,274 @@
+//! Structured dump of an object
+//!
+//! [`dump`] walks the class the object was created as down to DynamicObjectBase,
+//! giving every level and field with its offset inside the allocation,
+//! and the values of the fields implementing `Debug`.
+//! Render the [`Dump`] with `Display` for a text tree, or with [`Dump::to_json`].
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! #[subclass(Class, parent)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let object = Object::<Derived>::new(Box::new(Derived { field: 1, parent: Class { value: 2, foo: 3 } }));
+//! let dump = inspect::dump(&object.cast::<Class>());
+//! assert!(dump.root.fields[0].value.as_deref() == Some("1"));
+//! assert!(dump.root.parent.as_ref().unwrap().class.name() == "Class");
+//! println!("{}", dump);
+//! ```
+use core::{cell::Cell, fmt::{self, Write}, ops::Deref};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use crate::{format::FormatFn, reflect::levels, Class, ClassInfo, Object};
+
+/// Layout and values of an object, returned by [`dump`]
+pub struct Dump {
+      /// The class the object was created as
+      pub class: &'static ClassInfo,
+      /// The class the object is currently cast to
+      pub seen_as: &'static ClassInfo,
+      /// Offset of the 'seen_as' view from the start of the allocation
+      pub cast_offset: isize,
+      /// The level of 'class', holding its parent levels
+      pub root: Level,
+}
+
+/// One class of the inheritance chain of a dumped object
+pub struct Level {
+      pub class: &'static ClassInfo,
+      /// Offset of the struct from the start of the allocation
+      pub offset: usize,
+      pub fields: Vec<Field>,
+      /// The level of the parent class, None for DynamicObjectBase
+      pub parent: Option<Box<Level>>,
+}
+
+/// A field of a [`Level`]
+pub struct Field {
+      pub name: &'static str,
+      pub type_name: &'static str,
+      /// Offset of the field from the start of the allocation
+      pub offset: usize,
+      pub size: usize,
+      /// True for the field holding the parent, whose fields are in the parent level
+      pub holds_parent: bool,
+      /// Debug of the value, None if the type does not implement it or the field holds the parent
+      pub value: Option<String>,
+}
+
+/// Formats a field with its `debug` function, remembering if it had none
+struct FieldDebug {
+      debug: FormatFn,
+      field: *const u8,
+      missing: Cell<bool>,
+}
+
+impl fmt::Display for FieldDebug {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match unsafe { (self.debug)(self.field, f) } {
+                  Some(result) => result,
+                  None => {
+                        self.missing.set(true);
+                        Ok(())
+                  }
+            }
+      }
+}
+
+/// Dump the layout and fields of 'object'
+pub fn dump<T: Class, C: Deref>(object: &Object<T, C>) -> Dump {
+      let base = object.base();
+      let chain: Vec<_> = levels(object.class).collect();
+      let root = chain.iter().rev().fold(None, |parent, &(class, offset)| {
+            let fields = class.fields.iter().map(|field| {
+                  let holdsParent = field.parent.is_some();
+                  let value = if holdsParent {
+                        None
+                  } else {
+                        let debug = FieldDebug {
+                              debug: field.debug,
+                              field: base.wrapping_add(offset + field.offset),
+                              missing: Cell::new(false),
+                        };
+                        let mut value = String::new();
+                        let _ = write!(value, "{}", debug);
+                        (!debug.missing.get()).then_some(value)
+                  };
+                  Field {
+                        name: field.name,
+                        type_name: field.type_name(),
+                        offset: offset + field.offset,
+                        size: field.size,
+                        holds_parent: holdsParent,
+                        value,
+                  }
+            }).collect();
+            Some(Box::new(Level {
+                  class,
+                  offset,
+                  fields,
+                  parent,
+            }))
+      });
+
+      Dump {
+            class: object.class,
+            seen_as: T::info(),
+            cast_offset: object.offset as isize,
+            root: *root.expect("the chain holds at least the class of the object"),
+      }
+}
+
+impl fmt::Display for Dump {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            writeln!(f, "{} as {} (cast offset {})", self.class.name(), self.seen_as.name(), self.cast_offset)?;
+            let mut level = Some(&self.root);
+            let mut depth = 1;
+            while let Some(current) = level {
+                  let indent = depth * 2;
+                  writeln!(f, "{:indent$}{} @{}", "", current.class.name(), current.offset)?;
+                  for field in &current.fields {
+                        write!(f, "{:indent$}  {}: {} @{} ({} bytes)", "", field.name, field.type_name, field.offset, field.size)?;
+                        match field.value {
+                              Some(ref value) => writeln!(f, " = {}", value)?,
+                              None => writeln!(f)?,
+                        }
+                  }
+                  level = current.parent.as_deref();
+                  depth += 1;
+            }
+            Ok(())
+      }
+}
+
+/// Write 'value' as a JSON string
+fn jsonString(out: &mut String, value: &str) {
+      out.push('"');
+      for c in value.chars() {
+            match c {
+                  '"' => out.push_str("\\\""),
+                  '\\' => out.push_str("\\\\"),
+                  '\n' => out.push_str("\\n"),
+                  '\r' => out.push_str("\\r"),
+                  '\t' => out.push_str("\\t"),
+                  c if (c as u32) < 0x20 => {
+                        let _ = write!(out, "\\u{:04x}", c as u32);
+                  },
+                  c => out.push(c),
+            }
+      }
+      out.push('"');
+}
+
+impl Level {
+      fn json(&self, out: &mut String) {
+            out.push_str("{\"class\":");
+            jsonString(out, self.class.path());
+            let _ = write!(out, ",\"offset\":{},\"fields\":[", self.offset);
+            for (i, field) in self.fields.iter().enumerate() {
+                  if i > 0 {
+                        out.push(',');
+                  }
+                  out.push_str("{\"name\":");
+                  jsonString(out, field.name);
+                  out.push_str(",\"type\":");
+                  jsonString(out, field.type_name);
+                  let _ = write!(out, ",\"offset\":{},\"size\":{},\"holds_parent\":{}", field.offset, field.size, field.holds_parent);
+                  if let Some(ref value) = field.value {
+                        out.push_str(",\"value\":");
+                        jsonString(out, value);
+                  }
+                  out.push('}');
+            }
+            out.push(']');
+            if let Some(ref parent) = self.parent {
+                  out.push_str(",\"parent\":");
+                  parent.json(out);
+            }
+            out.push('}');
+      }
+}
+
+impl Dump {
+      /// Render as JSON, levels nested under "parent"
+      pub fn to_json(&self) -> String {
+            let mut out = String::from("{\"class\":");
+            jsonString(&mut out, self.class.path());
+            out.push_str(",\"seen_as\":");
+            jsonString(&mut out, self.seen_as.path());
+            let _ = write!(out, ",\"cast_offset\":{},\"root\":", self.cast_offset);
+            self.root.json(&mut out);
+            out.push('}');
+            out
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::{format, prelude::rust_2021::*};
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase)]
+      struct Base {
+            name: &'static str,
+            opaque: Opaque,
+      }
+
+      struct Opaque;
+
+      #[subclass(Base, parent)]
+      #[repr(C)]
+      struct Derived {
+            id: u64,
+            parent: Base,
+      }
+
+      fn object() -> Object<Base> {
+            Object::<Derived>::new(Box::new(Derived { id: 7, parent: Base { name: "a\"b", opaque: Opaque } })).cast()
+      }
+
+      #[test]
+      fn levels() {
+            let dump = dump(&object());
+            assert!(dump.class.name() == "Derived" && dump.seen_as.name() == "Base");
+            assert!(dump.cast_offset == 8);
+
+            let derived = &dump.root;
+            assert!(derived.offset == 0);
+            assert!(derived.fields[0].value.as_deref() == Some("7"));
+            assert!(derived.fields[1].holds_parent && derived.fields[1].value.is_none());
+
+            let base = derived.parent.as_ref().unwrap();
+            assert!(base.class.name() == "Base" && base.offset == 8);
+            assert!(base.fields[0].offset == 8 && base.fields[0].value.as_deref() == Some(r#""a\"b""#));
+            // Opaque has no Debug
+            assert!(base.fields[1].value.is_none());
+            assert!(base.parent.as_ref().unwrap().parent.is_none());
+
+            let text = format!("{}", dump);
+            assert!(text.starts_with("Derived as Base (cast offset 8)\n  Derived @0\n    id: u64 @0 (8 bytes) = 7\n"));
+      }
+
+      #[test]
+      fn json() {
+            let json: serde_json::Value = serde_json::from_str(&dump(&object()).to_json()).unwrap();
+            assert!(json["cast_offset"] == 8);
+            assert!(json["root"]["fields"][0]["value"] == "7");
+            assert!(json["root"]["parent"]["fields"][0]["value"] == r#""a\"b""#);
+            assert!(json["root"]["parent"]["parent"]["class"] == "dynamic_object::DynamicObjectBase");
+      }
+}
@@ -0,0 +1,260 @@
+//! Class invariants
+//!
+//! `#[invariant(path)]` on a #[subclass] struct registers `path(&Self) -> bool`, which must hold for
+//! the class and every class inheriting from it. They are checked parents first:
+//! - in debug builds, panicking with the class and the invariant, by [`Object::new`], [`Object::cast`],
+//!   [`Object::try_cast`], [`Object::create`], [`Object::checked`], `build_object` of the builders
+//!   and when the guard returned by [`Object::edit`] is dropped
+//! - in every build, failing with an error, when reading objects with [`binary`](crate::binary),
+//!   `serialization` or `graph`, and when creating them with [`Factory`](crate::factory::Factory)
+//!
+//! Mutation through `DerefMut` is not checked: use [`Object::edit`] for checked edits.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! #[invariant(Class::ordered)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! impl Class {
+//!       fn ordered(&self) -> bool {
+//!             self.value <= self.foo
+//!       }
+//! }
+//!
+//! #[subclass(Class, parent)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let mut object = Object::<Derived>::new(Box::new(Derived { field: 0, parent: Class { value: 1, foo: 2 } }));
+//! // Not checked
+//! object.parent.value = 3;
+//! let error = object.check_invariants().unwrap_err();
+//! assert!(error.class.name() == "Class" && error.invariant == "Class::ordered");
+//! ```
+use core::{fmt, ops::{Deref, DerefMut}};
+
+use crate::{reflect::levels, Class, ClassInfo, Object};
+
+/// A condition registered by `#[invariant]`
+pub struct Invariant {
+      #[doc(hidden)]
+      pub name: &'static str,
+      #[doc(hidden)]
+      pub check: unsafe fn(object: *const u8) -> bool,
+}
+
+impl Invariant {
+      /// Path of the function, as written in the attribute
+      pub fn name(&self) -> &'static str {
+            self.name
+      }
+}
+
+/// An invariant that does not hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvariantError {
+      /// The class declaring the invariant
+      pub class: &'static ClassInfo,
+      pub invariant: &'static str,
+}
+
+impl fmt::Display for InvariantError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "invariant {} of class {} does not hold", self.invariant, self.class.path())
+      }
+}
+
+impl ClassInfo {
+      /// Invariants declared by the class itself, not the inherited ones
+      pub fn invariants(&self) -> &'static [Invariant] {
+            self.invariants
+      }
+}
+
+/// Check the invariants of 'class' and its parents, parents first, on the object at 'base'
+pub(crate) fn check(class: &'static ClassInfo, base: *const u8) -> Result<(), InvariantError> {
+      let chain: alloc::vec::Vec<_> = levels(class).collect();
+      for (class, offset) in chain.into_iter().rev() {
+            for invariant in class.invariants {
+                  if !unsafe { (invariant.check)(base.wrapping_add(offset)) } {
+                        return Err(InvariantError {
+                              class,
+                              invariant: invariant.name,
+                        });
+                  }
+            }
+      }
+      Ok(())
+}
+
+/// Panic if an invariant does not hold, in debug builds only
+pub(crate) fn debugCheck(class: &'static ClassInfo, base: *const u8) {
+      if cfg!(debug_assertions) {
+            if let Err(err) = check(class, base) {
+                  panic!("{}", err);
+            }
+      }
+}
+
+impl<T: Class, C: Deref> Object<T, C> {
+      /// Check the invariants of the class the object was created as and of its parents
+      pub fn check_invariants(&self) -> Result<(), InvariantError> {
+            check(self.class, self.base())
+      }
+
+      /// Panic if an invariant does not hold, in debug builds only
+      pub fn checked(self) -> Self {
+            debugCheck(self.class, self.base());
+            self
+      }
+}
+
+impl<T: Class, C: DerefMut> Object<T, C> {
+      /// Mutably borrow the object, checking the invariants in debug builds once the guard is dropped
+      pub fn edit(&mut self) -> Edit<'_, T, C> {
+            Edit {
+                  object: self
+            }
+      }
+}
+
+/// Mutable borrow returned by [`Object::edit`]
+pub struct Edit<'a, T: Class, C: DerefMut> {
+      object: &'a mut Object<T, C>,
+}
+
+impl<T: Class, C: DerefMut> Deref for Edit<'_, T, C> {
+      type Target = T;
+
+      fn deref(&self) -> &T {
+            self.object
+      }
+}
+
+impl<T: Class, C: DerefMut> DerefMut for Edit<'_, T, C> {
+      fn deref_mut(&mut self) -> &mut T {
+            self.object
+      }
+}
+
+impl<T: Class, C: DerefMut> Drop for Edit<'_, T, C> {
+      fn drop(&mut self) {
+            debugCheck(self.object.class, self.object.base());
+      }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::{panic::{catch_unwind, AssertUnwindSafe}, prelude::rust_2021::*};
+      use dynamic_object_derive::{invariant, subclass};
+      use crate::{self as dynamic_object, binary, Class, DynamicObjectBase, Object};
+      use super::InvariantError;
+
+      #[subclass(DynamicObjectBase, binary)]
+      #[invariant(Range::ordered)]
+      struct Range {
+            start: u32,
+            end: u32,
+      }
+
+      impl Range {
+            fn ordered(&self) -> bool {
+                  self.start <= self.end
+            }
+      }
+
+      #[invariant(Bounded::bounded)]
+      #[invariant(Bounded::nonzero)]
+      #[subclass(Range, parent, binary)]
+      struct Bounded {
+            parent: Range,
+            max: u32,
+      }
+
+      impl Bounded {
+            fn bounded(&self) -> bool {
+                  self.parent.end <= self.max
+            }
+
+            fn nonzero(&self) -> bool {
+                  self.max > 0
+            }
+      }
+
+      fn bounded(start: u32, end: u32, max: u32) -> Object<Bounded> {
+            Object::<Bounded>::new(Box::new(Bounded { parent: Range { start, end }, max }))
+      }
+
+      fn panicMessage(run: impl FnOnce()) -> String {
+            let error = catch_unwind(AssertUnwindSafe(run)).unwrap_err();
+            error.downcast_ref::<String>().cloned().unwrap_or_default()
+      }
+
+      #[test]
+      fn inherited() {
+            assert!(Bounded::info().invariants().len() == 2);
+            let mut object = bounded(1, 2, 3);
+            assert!(object.check_invariants().is_ok());
+
+            object.parent.start = 5;
+            let error = object.check_invariants().unwrap_err();
+            // Parents first
+            assert!(error.class.name() == "Range" && error.invariant == "Range::ordered");
+            object.parent.start = 1;
+            object.max = 0;
+            assert!(object.check_invariants().unwrap_err().invariant == "Bounded::bounded");
+            object.parent.start = 0;
+            object.parent.end = 0;
+            assert!(object.check_invariants().unwrap_err().invariant == "Bounded::nonzero");
+      }
+
+      #[test]
+      fn checked() {
+            // Object::new checks
+            let message = panicMessage(|| {
+                  bounded(2, 1, 3);
+            });
+            assert!(message.contains("Range::ordered") && message.contains("Range"));
+
+            let mut object = bounded(1, 2, 3).cast::<Range>().checked();
+            object.edit().end = 3;
+            let message = panicMessage(move || {
+                  object.edit().end = 4;
+            });
+            assert!(message == "invariant Bounded::bounded of class dynamic_object::invariant::test::Bounded does not hold");
+
+            // DerefMut does not check, casts do
+            let mut object = bounded(1, 2, 3);
+            object.parent.end = 4;
+            let message = panicMessage(move || {
+                  object.cast::<Range>();
+            });
+            assert!(message.contains("Bounded::bounded"));
+            let mut object = bounded(1, 2, 3).cast::<Range>();
+            object.end = 4;
+            let message = panicMessage(move || {
+                  object.try_cast::<Bounded>();
+            });
+            assert!(message.contains("Bounded::bounded"));
+      }
+
+      #[test]
+      fn read() {
+            let bytes = binary::write(&bounded(1, 2, 3)).unwrap();
+            assert!(binary::read::<Range>(&bytes).is_ok());
+            let mut object = bounded(1, 2, 3);
+            object.parent.end = 4;
+            let bytes = binary::write(&object).unwrap();
+            let error = binary::read::<Range>(&bytes).err().unwrap();
+            assert!(matches!(error, binary::Error::Invariant(InvariantError { invariant: "Bounded::bounded", .. })));
+      }
+}
@@ -0,0 +1,207 @@
+//! Layout fingerprints
+//!
+//! `#[subclass]` hashes the name, type, size, alignment and offset of every field, the size and alignment
+//! of the struct and the fingerprint of its parent into [`Class::LAYOUT_HASH`](crate::Class::LAYOUT_HASH).
+//! Two builds agreeing on it agree on where the parent and each field are, which is what casting needs.
+//! Types of fields are hashed as written, and by size and alignment, not by their own layout.
+//!
+//! Objects crossing a build boundary in memory (plugins, shared memory) go through [`import`],
+//! which verifies the fingerprint sent along with them.
+//! Persisted data does not depend on the layout: [`binary`](crate::binary) checks the schema of the classes,
+//! and the `serialization` and `graph` modules check nothing, relying on serde
+//! to report missing or unknown fields.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! struct Shared {
+//!       value: u32
+//! }
+//!
+//! // Sent along with the objects by the other build
+//! let (path, hash) = (Shared::info().path(), Shared::LAYOUT_HASH);
+//! let object: Box<dyn Dyn> = Box::new(Shared { value: 1 });
+//! let object = unsafe { layout::import::<DynamicObjectBase>(object, path, hash) }.unwrap();
+//! assert!(object.cast::<Shared>().value == 1);
+//!
+//! let object: Box<dyn Dyn> = Box::new(Shared { value: 1 });
+//! assert!(unsafe { layout::import::<DynamicObjectBase>(object, path, hash + 1) }.is_err());
+//! ```
+use core::{fmt, marker::PhantomData};
+use alloc::boxed::Box;
+
+use crate::{reflect::levels, registry, Class, ClassInfo, Dyn, Object};
+
+/// Fingerprint of DynamicObjectBase, which every other one starts from
+pub const ROOT: u64 = hash_str(0xcbf2_9ce4_8422_2325, "dynamic_object::DynamicObjectBase");
+
+/// Why a class cannot accept objects from another build
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+      /// No class of this path is linked in the program
+      UnknownClass,
+      /// The class exists with a different layout
+      Mismatch { expected: u64, found: u64 },
+      /// The class does not inherit from the class asked for
+      NotSubclass,
+}
+
+impl fmt::Display for LayoutError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  LayoutError::UnknownClass => write!(f, "unknown class"),
+                  LayoutError::Mismatch { expected, found } => write!(f, "layout hash {:#x} does not match {:#x}", found, expected),
+                  LayoutError::NotSubclass => write!(f, "class is not a subclass"),
+            }
+      }
+}
+
+/// Add 'bytes' to the FNV-1a hash 'hash'
+pub const fn hash_bytes(mut hash: u64, bytes: &[u8]) -> u64 {
+      let mut i = 0;
+      while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+      }
+      // Separates consecutive strings
+      hash ^= 0xff;
+      hash.wrapping_mul(0x0100_0000_01b3)
+}
+
+pub const fn hash_str(hash: u64, value: &str) -> u64 {
+      hash_bytes(hash, value.as_bytes())
+}
+
+pub const fn hash_usize(hash: u64, value: usize) -> u64 {
+      hash_u64(hash, value as u64)
+}
+
+pub const fn hash_u64(hash: u64, value: u64) -> u64 {
+      hash_bytes(hash, &value.to_le_bytes())
+}
+
+/// Check that the class at 'path' has the layout fingerprint 'hash' in this build
+pub fn verify(path: &str, hash: u64) -> Result<&'static ClassInfo, LayoutError> {
+      let class = registry::all().find(|class| class.path() == path).ok_or(LayoutError::UnknownClass)?;
+      match class.layout_hash() {
+            expected if expected == hash => Ok(class),
+            expected => Err(LayoutError::Mismatch { expected, found: hash }),
+      }
+}
+
+/// Take an object made by another build, of the class at 'path' with the fingerprint 'hash' in that build, seen as 'T'
+///
+/// # Safety
+/// 'object' must hold a value of the class at 'path', as the other build laid it out,
+/// from an allocator this build can free
+pub unsafe fn import<T: Class>(object: Box<dyn Dyn>, path: &str, hash: u64) -> Result<Object<T>, LayoutError> {
+      let class = verify(path, hash)?;
+      let offset = levels(class)
+            .find(|(level, _)| *level == T::info())
+            .map(|(_, offset)| offset)
+            .ok_or(LayoutError::NotSubclass)?;
+      Ok(Object {
+            object,
+            class,
+            offset: offset as i16,
+            _marker: PhantomData
+      })
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use dynamic_object_derive::subclass;
+      use std::prelude::rust_2021::*;
+      use crate::{self as dynamic_object, Class, ClassInfo, Dyn, DynamicObjectBase};
+
+      mod v1 {
+            use super::*;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Base {
+                  pub value: u32
+            }
+
+            #[subclass(Base, parent)]
+            pub struct Derived {
+                  pub parent: Base,
+                  pub field: u16,
+            }
+      }
+
+      mod v2 {
+            use super::*;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Base {
+                  pub value: u64
+            }
+
+            #[subclass(Base, parent)]
+            pub struct Derived {
+                  pub parent: Base,
+                  pub field: u16,
+            }
+      }
+
+      mod retyped {
+            use super::*;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Base {
+                  pub value: f32
+            }
+      }
+
+      mod renamed {
+            use super::*;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Base {
+                  pub other: u32
+            }
+      }
+
+      #[test]
+      fn fingerprints() {
+            let hash = |class: &ClassInfo| class.layout_hash();
+            assert!(hash(DynamicObjectBase::info()) == super::ROOT);
+            assert!(v1::Base::LAYOUT_HASH == hash(v1::Base::info()));
+            assert!(hash(v1::Base::info()) != hash(v2::Base::info()));
+            assert!(hash(v1::Base::info()) != hash(renamed::Base::info()));
+            // Same size and alignment
+            assert!(hash(v1::Base::info()) != hash(retyped::Base::info()));
+            // Only the parent changed
+            assert!(hash(v1::Derived::info()) != hash(v2::Derived::info()));
+      }
+
+      #[test]
+      fn verify() {
+            let path = v1::Derived::info().path();
+            assert!(super::verify(path, v1::Derived::LAYOUT_HASH) == Ok(v1::Derived::info()));
+            assert!(super::verify(path, v2::Derived::LAYOUT_HASH) == Err(super::LayoutError::Mismatch {
+                  expected: v1::Derived::LAYOUT_HASH,
+                  found: v2::Derived::LAYOUT_HASH,
+            }));
+            assert!(super::verify("nowhere::Derived", 0) == Err(super::LayoutError::UnknownClass));
+      }
+
+      #[test]
+      fn import() {
+            let object: Box<dyn Dyn> = Box::new(v1::Derived { parent: v1::Base { value: 1 }, field: 2 });
+            let object = unsafe { super::import::<v1::Base>(object, v1::Derived::info().path(), v1::Derived::LAYOUT_HASH) }.unwrap();
+            assert!(object.value == 1 && object.class() == v1::Derived::info());
+            assert!(object.cast::<v1::Derived>().field == 2);
+
+            let object: Box<dyn Dyn> = Box::new(v1::Base { value: 1 });
+            let error = unsafe { super::import::<v1::Derived>(object, v1::Base::info().path(), v1::Base::LAYOUT_HASH) }.err();
+            assert!(error == Some(super::LayoutError::NotSubclass));
+            let object: Box<dyn Dyn> = Box::new(v1::Base { value: 1 });
+            let error = unsafe { super::import::<v1::Base>(object, v1::Base::info().path(), v2::Base::LAYOUT_HASH) }.err();
+            assert!(matches!(error, Some(super::LayoutError::Mismatch { .. })));
+      }
+}
@@ -6,9 +6,40 @@
 use core::{marker::PhantomData, ops::{Deref, DerefMut}};
 pub use dynamic_object_derive::*;
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 use alloc::boxed::Box;
 pub mod typing;
 pub use typing::*;
+pub mod sealed;
+pub use sealed::SealedHierarchy;
+pub mod visit;
+pub use visit::{Visitable, VisitableMut};
+pub mod multimethod;
+pub mod class_map;
+pub mod registry;
+pub use registry::ClassInfo;
+pub mod factory;
+pub mod reflect;
+pub mod value;
+pub mod method;
+pub mod binary;
+pub mod layout;
+pub mod compare;
+pub mod format;
+pub mod inspect;
+pub mod diff;
+pub mod snapshot;
+pub mod invariant;
+pub mod lifecycle;
+pub use lifecycle::Lifecycle;
+pub mod builder;
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(feature = "graph")]
+pub mod graph;
+#[doc(hidden)]
+pub mod __private;
 
 pub trait Dyn {
 
@@ -22,10 +53,14 @@ impl<T> Dyn for T {
 pub trait Class {
       type Parent: Sized + Class;
       const NAME: &'static str;
+      /// Fingerprint of the layout of the class and its parents, see [`layout`]
+      const LAYOUT_HASH: u64;
 
       fn id() -> usize;
       fn offset() -> isize;
       fn isa(id: usize) -> bool;
+      /// Runtime information, also listed by the registry
+      fn info() -> &'static ClassInfo;
 }
 
 /// An object
@@ -35,49 +70,88 @@ pub struct Object<T: Class, ContainerT = Box<dyn Dyn>> {
       // A pointer to the object created
       // It should *always* point to the object created so it can be correctly freed
       object: ContainerT,
-      isa: fn(id: usize) -> bool,
+      // The class the object was created as
+      class: &'static ClassInfo,
       offset: i16,
       _marker: PhantomData<T>
 }
 
 impl<T: Class, ContainerT> Object<T, ContainerT> {
-      /// Constructs an object from a container
+      /// Object of class 'T' in 'object', without checking the invariants
+      pub(crate) fn fromContainer(object: ContainerT) -> Self {
+            Self {
+                  object,
+                  class: T::info(),
+                  offset: 0,
+                  _marker: PhantomData
+            }
+      }
+
+      /// Check if the object is a child of Other or is type Other
       /// 
       /// # Example:
       /// ```
+      /// # use dynamic_object::*;
       /// #[subclass(DynamicObjectBase)]
       /// struct MyObject;
       /// 
       /// let object = Object::<MyObject>::new(Box::new(MyObject {}));
+      /// assert!(object.isa::<DynamicObjectBase>());
       /// ```
-      pub fn new(object: ContainerT) -> Self {
-            Self {
-                  object,
-                  isa: T::isa,
-                  offset: 0,
-                  _marker: PhantomData
+      pub fn isa<Other: Class>(&self) -> bool {
+            (self.class.isa)(Other::id())
+      }
+
+      /// Id of the class the object was created as, whatever it has been cast to since
+      pub fn dynamic_id(&self) -> usize {
+            self.class.id()
+      }
+
+      /// The class the object was created as, whatever it has been cast to since
+      pub fn class(&self) -> &'static ClassInfo {
+            self.class
+      }
+
+      /// The object seen as 'Cast', without checking that it is one nor the invariants
+      pub(crate) fn reinterpret<Cast: Class>(self) -> Object<Cast, ContainerT> {
+            Object {
+                  object: self.object,
+                  class: self.class,
+                  _marker: PhantomData,
+                  offset: self.offset + castOffset::<T, Cast>()
             }
       }
 
-      /// Check if the object is a child of Other or is type Other
+      pub fn vtable(&self) -> &ContainerT {
+            &self.object
+      }
+
+      pub fn vtable_mut(&mut self) -> &mut ContainerT {
+            &mut self.object
+      }
+}
+
+impl<T: Class, Container: Deref> Object<T, Container> {
+      /// Constructs an object from a container, checking the invariants in debug builds
       /// 
       /// # Example:
       /// ```
-      ///  #[subclass(DynamicObjectBase)]
+      /// # use dynamic_object::*;
+      /// #[subclass(DynamicObjectBase)]
       /// struct MyObject;
       /// 
       /// let object = Object::<MyObject>::new(Box::new(MyObject {}));
-      /// assert!(object.isa::<DynamicObjectBase::>());
       /// ```
-      pub fn isa<Other: Class>(&self) -> bool {
-            (self.isa)(Other::id())
+      pub fn new(object: Container) -> Self {
+            Self::fromContainer(object).debugChecked()
       }
 
       /// Cast to type 'Cast'
-      /// panic if 'self' does not inherit from 'Cast'/ is not 'Cast'
+      /// panic if 'self' does not inherit from 'Cast'/ is not 'Cast', or in debug builds if an invariant does not hold
       /// 
       ///  # Example:
       /// ```
+      /// # use dynamic_object::*;
       /// #[subclass(DynamicObjectBase)]
       /// struct Class {
       ///       value: u32,
@@ -112,53 +186,133 @@ impl<T: Class, ContainerT> Object<T, ContainerT> {
       /// assert!(object.parent.value == 548389);
       /// assert!(object.parent.foo == 72840548);
       /// ```
-      pub fn cast<Cast: Class>(self) -> Object<Cast, ContainerT> {
+      pub fn cast<Cast: Class>(self) -> Object<Cast, Container> {
             if isSubclassOf::<Cast, T>() {
                   assert!(self.isa::<Cast>());
             }
-            let offset = if isSubclassOf::<Cast, T>() {
-                  -typing::offsetOf::<T, Cast>()
-            }
-            else {
-                  typing::offsetOf::<Cast, T>()
-            } as i16;
-            Object {
-                  object: self.object,
-                  isa: self.isa,
-                  _marker: PhantomData,
-                  offset: self.offset + offset
-            }
+            self.reinterpret::<Cast>().debugChecked()
       }
 
-      pub fn vtable(&self) -> &ContainerT {
-            &self.object
+      /// Try to cast to 'Cast', checking the invariants in debug builds
+      pub fn try_cast<Cast: Class>(self) -> Option<Object<Cast, Container>> {
+            if isSubclassOf::<Cast, T>() && !self.isa::<Cast>() {
+                  return None
+            }
+            Some(self.reinterpret::<Cast>().debugChecked())
       }
 
-      pub fn vtable_mut(&mut self) -> &mut ContainerT {
-            &mut self.object
+      /// Panic if an invariant does not hold, in debug builds only
+      fn debugChecked(self) -> Self {
+            #[cfg(debug_assertions)]
+            invariant::debugCheck(self.class, self.base());
+            self
       }
 
-      /// Try to cast to 'Cast'
-      pub fn try_cast<Cast: Class>(self) -> Option<Object<Cast, ContainerT>> {
-            if isSubclassOf::<Cast, T>() {
-                  if !self.isa::<Cast>() {
-                        return None
-                  }
+      /// Borrow the object as 'Cast' without consuming it
+      /// panic if 'self' does not inherit from 'Cast'/ is not 'Cast'
+      pub fn cast_ref<Cast: Class>(&self) -> &Cast {
+            match self.try_cast_ref::<Cast>() {
+                  Some(object) => object,
+                  None => panic!("object is not a {}", core::any::type_name::<Cast>())
             }
-            
-            let offset = if isSubclassOf::<Cast, T>() {
-                  -typing::offsetOf::<T, Cast>()
+      }
+
+      /// Address of the object created, whatever it has been cast to since
+      pub(crate) fn base(&self) -> *const u8 {
+            &*self.object as *const Container::Target as *const u8
+      }
+
+      /// Deep copy into a new box of the class the object was created as, cast like 'self'
+      /// panic if that class does not have the `clone` option
+      ///
+      /// # Example:
+      /// ```
+      /// # use dynamic_object::*;
+      /// #[subclass(DynamicObjectBase, clone)]
+      /// #[derive(Clone)]
+      /// struct Class {
+      ///       value: u32
+      /// }
+      /// #[subclass(Class, parent, clone)]
+      /// #[derive(Clone)]
+      /// struct Derived {
+      ///       field: u32,
+      ///       parent: Class,
+      /// }
+      ///
+      /// let object = Object::<Derived>::new(Box::new(Derived { field: 1, parent: Class { value: 2 } })).cast::<Class>();
+      /// let copy = object.clone_object();
+      /// assert!(copy.value == 2);
+      /// assert!(copy.cast::<Derived>().field == 1);
+      /// ```
+      pub fn clone_object(&self) -> Object<T> {
+            match self.try_clone_object() {
+                  Some(object) => object,
+                  None => panic!("class {} does not have the clone option", self.class.path())
             }
-            else {
-                  typing::offsetOf::<Cast, T>()
-            } as i16;
+      }
+
+      /// Try to deep copy the object, None if its class does not have the `clone` option
+      pub fn try_clone_object(&self) -> Option<Object<T>> {
+            let clone = self.class.clone?;
             Some(Object {
-                  object: self.object,
-                  isa: self.isa,
-                  _marker: PhantomData,
-                  offset: self.offset + offset
+                  object: unsafe { clone(self.base()) },
+                  class: self.class,
+                  offset: self.offset,
+                  _marker: PhantomData
             })
       }
+
+      /// Try to borrow the object as 'Cast'
+      pub fn try_cast_ref<Cast: Class>(&self) -> Option<&Cast> {
+            if !self.isa::<Cast>() {
+                  return None
+            }
+            let inner = &**self as *const T as usize;
+            let inner = inner.wrapping_add(castOffset::<T, Cast>() as usize);
+            unsafe {
+                  Some(&*(inner as *const Cast))
+            }
+      }
+}
+
+impl<T: Class, Container: DerefMut> Object<T, Container> {
+      /// Mutably borrow the object as 'Cast' without consuming it
+      /// panic if 'self' does not inherit from 'Cast'/ is not 'Cast'
+      pub fn cast_mut<Cast: Class>(&mut self) -> &mut Cast {
+            match self.try_cast_mut::<Cast>() {
+                  Some(object) => object,
+                  None => panic!("object is not a {}", core::any::type_name::<Cast>())
+            }
+      }
+
+      /// Mutable address of the object created, whatever it has been cast to since
+      pub(crate) fn baseMut(&mut self) -> *mut u8 {
+            &mut *self.object as *mut Container::Target as *mut u8
+      }
+
+      /// Try to mutably borrow the object as 'Cast'
+      pub fn try_cast_mut<Cast: Class>(&mut self) -> Option<&mut Cast> {
+            if !self.isa::<Cast>() {
+                  return None
+            }
+            let inner = &mut **self as *mut T as usize;
+            let inner = inner.wrapping_add(castOffset::<T, Cast>() as usize);
+            unsafe {
+                  Some(&mut *(inner as *mut Cast))
+            }
+      }
+}
+
+/// Offset to add to a pointer to 'From' to get a pointer to 'To'
+pub(crate) fn castOffset<From: Class, To: Class>() -> i16 {
+      let offset = if isSubclassOf::<To, From>() {
+            -typing::offsetOf::<From, To>()
+      }
+      else {
+            typing::offsetOf::<To, From>()
+      };
+      offset as i16
 }
 
 impl<T: Class, Container: Deref> Deref for Object<T, Container> {
@@ -191,7 +345,7 @@ impl<T: Class, Container: Clone> Clone for Object<T, Container> {
       fn clone(&self) -> Self {
             Self { 
                   object: self.object.clone(), 
-                  isa: self.isa, 
+                  class: self.class,
                   offset: self.offset, 
                   _marker: PhantomData
             }
@@ -207,14 +361,20 @@ impl<T: Class, Container: Copy> Copy for Object<T, Container> {
 /// 
 /// # Example: 
 /// ```
+/// # use dynamic_object::*;
 /// #[subclass(DynamicObjectBase)]
 /// struct MyObject;
 /// ```
 pub struct DynamicObjectBase;
 
+impl __private::Sealed for DynamicObjectBase {
+      type Token = __private::Open;
+}
+
 impl Class for DynamicObjectBase {
       type Parent = Self;
       const NAME: &'static str = "dynamic::ObjectBase";
+      const LAYOUT_HASH: u64 = layout::ROOT;
 
       fn isa(id: usize) -> bool {
             id == Self::id()
@@ -227,8 +387,17 @@ impl Class for DynamicObjectBase {
       fn id() -> usize {
             Self::id as *const u8 as usize
       }
+
+      fn info() -> &'static ClassInfo {
+            &DYNAMIC_OBJECT_BASE
+      }
 }
 
+static DYNAMIC_OBJECT_BASE: ClassInfo = ClassInfo::ROOT;
+
+#[linkme::distributed_slice(registry::CLASSES)]
+static REGISTER_DYNAMIC_OBJECT_BASE: &ClassInfo = &DYNAMIC_OBJECT_BASE;
+
 #[cfg(test)]
 mod test {
       #![allow(unused_imports)]
@@ -258,6 +427,46 @@ mod test {
             parent: Class,
       }
 
+      #[subclass(DynamicObjectBase, clone)]
+      #[derive(Clone)]
+      struct Shape {
+            name: std::string::String
+      }
+
+      #[subclass(Shape, parent, clone, deref)]
+      #[derive(Clone)]
+      struct Circle {
+            radius: f32,
+            parent: Shape,
+      }
+
+      #[subclass(Circle, parent, deref)]
+      struct Ring {
+            parent: Circle,
+            width: f32,
+      }
+
+      // Parents not at offset 0
+      #[subclass(DynamicObjectBase)]
+      #[repr(C)]
+      struct Top {
+            x: u32
+      }
+
+      #[subclass(Top, parent)]
+      #[repr(C)]
+      struct Middle {
+            pad: u32,
+            parent: Top,
+      }
+
+      #[subclass(Middle, parent)]
+      #[repr(C)]
+      struct Bottom {
+            tag: u32,
+            parent: Middle,
+      }
+
       #[subclass(DynamicObjectBase)]
       struct BarObject {
 
@@ -308,4 +517,100 @@ mod test {
             assert!(object.parent.value == 548389);
             assert!(object.parent.foo == 72840548);
       }
+
+      fn derived() -> Object<Class> {
+            let object = Derived {
+                  parent: Class {
+                        value: 1,
+                        foo: 2
+                  },
+                  field: 3,
+            };
+            Object::<Derived>::new(Box::new(object)).cast::<Class>()
+      }
+
+      #[test]
+      fn cast_ref() {
+            let mut object = derived();
+            assert!(object.cast_ref::<Derived>().field == 3);
+            assert!(object.try_cast_ref::<BarObject>().is_none());
+            object.cast_mut::<Derived>().field = 4;
+            assert!(object.cast::<Derived>().field == 4);
+      }
+
+      #[test]
+      fn cast_nested_offsets() {
+            let bottom = Bottom { tag: 81, parent: Middle { pad: 3, parent: Top { x: 42 } } };
+            let mut object = Object::<Bottom>::new(Box::new(bottom));
+            assert!(object.cast_ref::<Middle>().pad == 3);
+            assert!(object.cast_ref::<Top>().x == 42);
+            assert!(object.cast_ref::<Bottom>().tag == 81);
+            object.cast_mut::<Top>().x = 43;
+
+            let middle = object.cast::<Middle>();
+            assert!(middle.pad == 3 && middle.cast_ref::<Middle>().pad == 3);
+            let top = middle.cast::<Top>();
+            assert!(top.x == 43);
+            assert!(top.cast_ref::<Middle>().pad == 3);
+            let bottom = top.cast::<Bottom>();
+            assert!(bottom.tag == 81 && bottom.parent.parent.x == 43);
+      }
+
+      #[test]
+      fn match_class() {
+            let mut object = derived();
+            let value = match_class!(&object {
+                  Derived(derived) => derived.field,
+                  Class(class) => class.value,
+                  _ => 0
+            });
+            assert!(value == 3);
+
+            match_class!(&mut object {
+                  Derived(derived) => derived.parent.foo = 5,
+                  _ => unreachable!()
+            });
+            assert!(object.foo == 5);
+
+            let object = match_class!(object {
+                  BarObject(_) => None,
+                  Derived(derived) => Some(derived),
+                  other => Some(other.cast::<Derived>())
+            });
+            assert!(object.unwrap().field == 3);
+
+            let object = Object::<Class>::new(Box::new(Class { value: 6, foo: 7 }));
+            let mut value = 0;
+            match_class!(&object {
+                  Derived(derived) => value = derived.field,
+                  Class(class) => value = class.value,
+            });
+            assert!(value == 6);
+      }
+
+      #[test]
+      fn clone_object() {
+            let circle = Circle { radius: 1.0, parent: Shape { name: "circle".into() } };
+            let mut object = Object::<Circle>::new(Box::new(circle)).cast::<Shape>();
+            let copy = object.clone_object();
+            object.name.push('!');
+            assert!(copy.isa::<Circle>());
+            assert!(copy.name == "circle");
+            assert!(copy.cast::<Circle>().radius == 1.0);
+
+            assert!(derived().try_clone_object().is_none());
+      }
+
+      #[test]
+      fn deref_parent() {
+            let circle = Circle { radius: 1.0, parent: Shape { name: "ring".into() } };
+            let mut ring = Ring { parent: circle, width: 0.5 };
+            assert!(ring.radius == 1.0 && ring.name == "ring");
+            ring.radius = 2.0;
+            ring.as_parent_mut().as_parent_mut().name.push('s');
+            assert!(ring.as_parent().radius == 2.0 && ring.parent.parent.name == "rings");
+
+            let object = Object::<Ring>::new(Box::new(ring));
+            assert!(object.name == "rings" && object.width == 0.5);
+      }
 }
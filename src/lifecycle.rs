@@ -0,0 +1,179 @@
+//! Construction and destruction hooks
+//!
+//! `#[subclass(.., lifecycle)]` registers the class's [`Lifecycle`] implementation.
+//! [`Object::create`] boxes a value and runs `on_init` from the root class down to the class of the value,
+//! then dropping the object runs `on_destroy` from that class up to the root, before the memory is freed.
+//! Classes of the chain without the option are skipped.
+//!
+//! The hooks run for objects made by [`Object::create`], in the default container or any other one
+//! the box is moved to. The other ways to make an object run none: [`Object::new`], [`Factory`](crate::Factory),
+//! the builders, reading objects with serde or [`binary`](crate::binary), [`Object::clone_object`]
+//! and the copies kept by [`Object::snapshot`].
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use std::{cell::RefCell, rc::Rc};
+//! #[subclass(DynamicObjectBase, lifecycle)]
+//! struct Class {
+//!       log: Rc<RefCell<Vec<&'static str>>>
+//! }
+//!
+//! impl Lifecycle for Class {
+//!       fn on_init(&mut self) { self.log.borrow_mut().push("init Class"); }
+//!       fn on_destroy(&mut self) { self.log.borrow_mut().push("destroy Class"); }
+//! }
+//!
+//! #[subclass(Class, parent, lifecycle)]
+//! struct Derived {
+//!       parent: Class,
+//! }
+//!
+//! impl Lifecycle for Derived {
+//!       fn on_init(&mut self) { self.parent.log.borrow_mut().push("init Derived"); }
+//!       fn on_destroy(&mut self) { self.parent.log.borrow_mut().push("destroy Derived"); }
+//! }
+//!
+//! let log = Rc::new(RefCell::new(Vec::new()));
+//! let object = Object::create(Derived { parent: Class { log: log.clone() } });
+//! drop(object.cast::<Class>());
+//! assert!(*log.borrow() == ["init Class", "init Derived", "destroy Derived", "destroy Class"]);
+//! ```
+use alloc::boxed::Box;
+
+use crate::{invariant, reflect::levels, Class, Object};
+
+/// Hooks run by objects made with [`Object::create`], registered by the `lifecycle` option
+pub trait Lifecycle {
+      /// Called once the value is in its final place, after the hooks of the parents
+      fn on_init(&mut self) {}
+
+      /// Called before the value is dropped, before the hooks of the parents
+      fn on_destroy(&mut self) {}
+}
+
+/// Value boxed by [`Object::create`], running the `on_destroy` hooks before it is dropped
+///
+/// Transparent, so the box still starts with the value
+#[repr(transparent)]
+struct Hooked<T: Class>(T);
+
+impl<T: Class> Drop for Hooked<T> {
+      fn drop(&mut self) {
+            let base = &mut self.0 as *mut T as *mut u8;
+            for (class, offset) in levels(T::info()) {
+                  if let Some(onDestroy) = class.on_destroy {
+                        unsafe { onDestroy(base.wrapping_add(offset)) }
+                  }
+            }
+      }
+}
+
+impl<T: Class + 'static> Object<T> {
+      /// Box 'value' and run the `on_init` hooks, from the root class down to 'T'
+      ///
+      /// Dropping the box, whatever the object is cast to, runs the `on_destroy` hooks
+      pub fn create(value: T) -> Self {
+            let mut value = Box::new(Hooked(value));
+            let base = &mut value.0 as *mut T as *mut u8;
+            let chain: alloc::vec::Vec<_> = levels(T::info()).collect();
+            for (class, offset) in chain.into_iter().rev() {
+                  if let Some(onInit) = class.on_init {
+                        unsafe { onInit(base.wrapping_add(offset)) }
+                  }
+            }
+            invariant::debugCheck(T::info(), base);
+            Object::<T>::fromContainer(value)
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::{cell::RefCell, format, prelude::rust_2021::*, rc::Rc};
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+      use super::*;
+
+      type Log = Rc<RefCell<Vec<String>>>;
+
+      #[subclass(DynamicObjectBase, lifecycle)]
+      struct Resource {
+            log: Log,
+            open: bool,
+      }
+
+      impl Lifecycle for Resource {
+            fn on_init(&mut self) {
+                  self.open = true;
+                  self.log.borrow_mut().push("open".into());
+            }
+
+            fn on_destroy(&mut self) {
+                  self.open = false;
+                  self.log.borrow_mut().push("close".into());
+            }
+      }
+
+      // No hooks of its own
+      #[subclass(Resource, parent)]
+      struct File {
+            parent: Resource,
+      }
+
+      #[subclass(File, parent, lifecycle)]
+      struct Socket {
+            parent: File,
+            port: u16,
+      }
+
+      impl Lifecycle for Socket {
+            fn on_init(&mut self) {
+                  // Parents are initialized first
+                  let open = self.parent.parent.open;
+                  self.parent.parent.log.borrow_mut().push(format!("listen {} {}", self.port, open));
+            }
+
+            fn on_destroy(&mut self) {
+                  let open = self.parent.parent.open;
+                  self.parent.parent.log.borrow_mut().push(format!("stop {} {}", self.port, open));
+            }
+      }
+
+      fn socket(log: &Log) -> Socket {
+            Socket { parent: File { parent: Resource { log: log.clone(), open: false } }, port: 80 }
+      }
+
+      #[test]
+      fn order() {
+            let log = Log::default();
+            let object = Object::create(socket(&log)).cast::<Resource>();
+            assert!(object.open);
+            assert!(*log.borrow() == ["open", "listen 80 true"]);
+            drop(object);
+            assert!(*log.borrow() == ["open", "listen 80 true", "stop 80 true", "close"]);
+      }
+
+      #[test]
+      fn containers() {
+            let log = Log::default();
+            // Works with the APIs taking the default container
+            let objects: Vec<Object<Resource>> = vec![Object::create(socket(&log)).cast()];
+            let copy = objects[0].cast_ref::<Socket>().port;
+            assert!(copy == 80 && log.borrow().len() == 2);
+            drop(objects);
+            assert!(*log.borrow() == ["open", "listen 80 true", "stop 80 true", "close"]);
+      }
+
+      #[test]
+      fn unmanaged() {
+            let log = Log::default();
+            drop(Object::<Socket>::new(Box::new(socket(&log))));
+            assert!(log.borrow().is_empty());
+
+            let object = Object::create(File { parent: Resource { log: log.clone(), open: false } });
+            drop(object);
+            assert!(*log.borrow() == ["open", "close"]);
+      }
+}
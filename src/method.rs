@@ -0,0 +1,306 @@
+//! Method reflection
+//!
+//! `#[reflect_methods]` on an impl block registers its `&self` and `&mut self` methods,
+//! which can then be called by name with [`Value`] arguments.
+//! Their parameters must implement [`FromValue`](crate::value::FromValue) and their return type `Into<Value>`:
+//! methods taking references, even inside type arguments like `Option<&str>`, or naming `Self` in a parameter
+//! are skipped, mark the others with `#[reflect(skip)]`.
+//! Methods are looked up from the dynamic type of the object to the root class, so overrides win.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use dynamic_object::value::Value;
+//! #[subclass(DynamicObjectBase)]
+//! struct Shape {
+//!       scale: f32
+//! }
+//!
+//! #[reflect_methods]
+//! impl Shape {
+//!       fn resize(&mut self, factor: f32) {
+//!             self.scale *= factor;
+//!       }
+//!
+//!       fn name(&self) -> String {
+//!             "shape".into()
+//!       }
+//!
+//!       fn rename(&mut self, _: &str) {}
+//!
+//!       #[reflect(skip)]
+//!       fn area(&self) -> Option<f32> {
+//!             None
+//!       }
+//! }
+//!
+//! #[subclass(Shape, shape)]
+//! struct Circle {
+//!       shape: Shape
+//! }
+//!
+//! #[reflect_methods]
+//! impl Circle {
+//!       fn name(&self) -> String {
+//!             "circle".into()
+//!       }
+//! }
+//!
+//! let mut object = Object::<Circle>::new(Box::new(Circle { shape: Shape { scale: 1.0 } })).cast::<Shape>();
+//! assert!(object.invoke("resize", &[Value::F32(2.0)]) == Ok(Value::Unit));
+//! assert!(object.scale == 2.0);
+//! assert!(object.invoke_ref("name", &[]) == Ok(Value::from("circle")));
+//! ```
+use core::{fmt, ops::{Deref, DerefMut}};
+
+use crate::{reflect::levels, value::Value, Class, ClassInfo, Object};
+
+/// Calls the method on the struct at 'object' which must be of the class of the method
+pub type Invoker = unsafe fn(object: *mut u8, args: &[Value]) -> Result<Value, InvokeError>;
+
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static METHODS: [MethodInfo];
+
+/// Why a method could not be called
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvokeError {
+      /// Neither the class nor its ancestors have a reflected method of this name
+      NoSuchMethod,
+      /// The method takes `&mut self` but was called through a shared reference
+      RequiresMut,
+      ArgumentCount { expected: usize, found: usize },
+      ArgumentType { index: usize, expected: &'static str, found: &'static str },
+}
+
+impl fmt::Display for InvokeError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  InvokeError::NoSuchMethod => write!(f, "no such method"),
+                  InvokeError::RequiresMut => write!(f, "method takes &mut self"),
+                  InvokeError::ArgumentCount { expected, found } => {
+                        write!(f, "expected {} arguments, found {}", expected, found)
+                  },
+                  InvokeError::ArgumentType { index, expected, found } => {
+                        write!(f, "argument {} should be {}, found {}", index, expected, found)
+                  },
+            }
+      }
+}
+
+/// A method registered by #[reflect_methods]
+pub struct MethodInfo {
+      #[doc(hidden)]
+      pub class: fn() -> &'static ClassInfo,
+      #[doc(hidden)]
+      pub name: &'static str,
+      /// Name and type of each parameter, 'self' excluded
+      #[doc(hidden)]
+      pub params: &'static [(&'static str, &'static str)],
+      #[doc(hidden)]
+      pub returns: &'static str,
+      #[doc(hidden)]
+      pub mutable: bool,
+      #[doc(hidden)]
+      pub invoke: Invoker,
+}
+
+impl MethodInfo {
+      pub fn name(&self) -> &'static str {
+            self.name
+      }
+
+      /// The class whose impl block declares the method
+      pub fn class(&self) -> &'static ClassInfo {
+            (self.class)()
+      }
+
+      /// Name and type, as written, of each parameter after 'self'
+      pub fn params(&self) -> &'static [(&'static str, &'static str)] {
+            self.params
+      }
+
+      /// Return type as written, "()" if none
+      pub fn returns(&self) -> &'static str {
+            self.returns
+      }
+
+      /// Check if the method takes `&mut self`
+      pub fn is_mut(&self) -> bool {
+            self.mutable
+      }
+}
+
+impl fmt::Debug for MethodInfo {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "fn {}(", self.name)?;
+            write!(f, "{}", if self.mutable { "&mut self" } else { "&self" })?;
+            for (name, ty) in self.params {
+                  write!(f, ", {}: {}", name, ty)?;
+            }
+            write!(f, ") -> {}", self.returns)
+      }
+}
+
+impl ClassInfo {
+      /// Reflected methods declared by the class itself
+      pub fn methods(&'static self) -> impl Iterator<Item = &'static MethodInfo> {
+            METHODS.iter().filter(move |method| method.class() == self)
+      }
+
+      /// The method called 'name' of this class, or of its nearest ancestor declaring one
+      pub fn find_method(&'static self, name: &str) -> Option<&'static MethodInfo> {
+            self.ancestors().find_map(|class| class.methods().find(|method| method.name == name))
+      }
+}
+
+/// The method called 'name' for an object of 'class', with the offset of the struct declaring it
+fn resolve(class: &'static ClassInfo, name: &str) -> Result<(&'static MethodInfo, usize), InvokeError> {
+      levels(class)
+            .find_map(|(class, offset)| {
+                  class.methods().find(|method| method.name == name).map(|method| (method, offset))
+            })
+            .ok_or(InvokeError::NoSuchMethod)
+}
+
+impl<T: Class, C: Deref> Object<T, C> {
+      /// Call a `&self` method of the dynamic type of the object
+      pub fn invoke_ref(&self, name: &str, args: &[Value]) -> Result<Value, InvokeError> {
+            let (method, offset) = resolve(self.class, name)?;
+            if method.mutable {
+                  return Err(InvokeError::RequiresMut);
+            }
+            let object = self.base().wrapping_add(offset) as *mut u8;
+            unsafe { (method.invoke)(object, args) }
+      }
+}
+
+impl<T: Class, C: DerefMut> Object<T, C> {
+      /// Call a method of the dynamic type of the object
+      pub fn invoke(&mut self, name: &str, args: &[Value]) -> Result<Value, InvokeError> {
+            let (method, offset) = resolve(self.class, name)?;
+            let object = self.baseMut().wrapping_add(offset);
+            unsafe { (method.invoke)(object, args) }
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::{reflect_methods, subclass};
+      use crate::{self as dynamic_object, Class, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase)]
+      struct Counter {
+            count: i32
+      }
+
+      #[reflect_methods]
+      impl Counter {
+            fn add(&mut self, amount: i32) -> i32 {
+                  self.count += amount;
+                  self.count
+            }
+
+            fn get(&self) -> i32 {
+                  self.count
+            }
+
+            fn describe(&self) -> String {
+                  format!("counter at {}", self.count)
+            }
+
+            // Not reflected
+            fn generic<T>(&self, _: T) {
+
+            }
+
+            fn associated() {
+
+            }
+
+            fn starts_with(&self, prefix: &str) -> bool {
+                  self.count.to_string().starts_with(prefix)
+            }
+
+            fn ends_with(&self, suffix: Option<&str>) -> bool {
+                  suffix.is_some_and(|suffix| self.count.to_string().ends_with(suffix))
+            }
+
+            fn merge(&mut self, other: Self) {
+                  self.count += other.count;
+            }
+
+            fn pair(&self, _: (u8, Vec<&Self>)) {
+
+            }
+
+            #[reflect(skip)]
+            fn positive(&self) -> Option<u64> {
+                  u64::try_from(self.count).ok()
+            }
+      }
+
+      #[subclass(Counter, counter)]
+      struct Labeled {
+            label: String,
+            counter: Counter
+      }
+
+      #[reflect_methods]
+      impl Labeled {
+            fn describe(&self) -> String {
+                  format!("{} at {}", self.label, self.counter.count)
+            }
+
+            fn rename(&mut self, label: String, suffix: char) {
+                  self.label = format!("{}{}", label, suffix);
+            }
+      }
+
+      fn labeled() -> Object<Counter> {
+            Object::<Labeled>::new(Box::new(Labeled { label: "clicks".into(), counter: Counter { count: 0 } })).cast()
+      }
+
+      #[test]
+      fn invoke() {
+            let mut object = labeled();
+            assert!(object.invoke("add", &[Value::I32(3)]) == Ok(Value::I32(3)));
+            assert!(object.invoke_ref("get", &[]) == Ok(Value::I32(3)));
+            assert!(object.invoke("describe", &[]) == Ok(Value::from("clicks at 3")));
+            assert!(object.invoke("rename", &[Value::from("taps"), Value::Char('!')]) == Ok(Value::Unit));
+            assert!(object.invoke_ref("describe", &[]) == Ok(Value::from("taps! at 3")));
+
+            let plain = Object::<Counter>::new(Box::new(Counter { count: 1 }));
+            assert!(plain.invoke_ref("describe", &[]) == Ok(Value::from("counter at 1")));
+      }
+
+      #[test]
+      fn errors() {
+            let mut object = labeled();
+            assert!(object.invoke("missing", &[]) == Err(InvokeError::NoSuchMethod));
+            assert!(object.invoke("generic", &[Value::Unit]) == Err(InvokeError::NoSuchMethod));
+            assert!(object.invoke_ref("starts_with", &[Value::from("1")]) == Err(InvokeError::NoSuchMethod));
+            assert!(object.invoke_ref("ends_with", &[Value::Unit]) == Err(InvokeError::NoSuchMethod));
+            assert!(object.invoke("merge", &[Value::Unit]) == Err(InvokeError::NoSuchMethod));
+            assert!(object.invoke_ref("positive", &[]) == Err(InvokeError::NoSuchMethod));
+            assert!(object.invoke_ref("add", &[Value::I32(1)]) == Err(InvokeError::RequiresMut));
+            assert!(object.invoke("add", &[]) == Err(InvokeError::ArgumentCount { expected: 1, found: 0 }));
+            assert!(object.invoke("add", &[Value::U8(1)]) == Err(InvokeError::ArgumentType { index: 0, expected: "i32", found: "u8" }));
+            assert!(object.count == 0);
+      }
+
+      #[test]
+      fn signatures() {
+            let names: Vec<_> = Counter::info().methods().map(MethodInfo::name).collect();
+            assert!(names.len() == 3);
+            let rename = Labeled::info().find_method("rename").unwrap();
+            assert!(rename.params() == [("label", "String"), ("suffix", "char")]);
+            assert!(rename.returns() == "()" && rename.is_mut());
+            assert!(Labeled::info().find_method("get").unwrap().class() == Counter::info());
+            assert!(std::format!("{:?}", Counter::info().find_method("add").unwrap()) == "fn add(&mut self, amount: i32) -> i32");
+      }
+}
@@ -0,0 +1,280 @@
+//! Multiple dispatch on the dynamic types of several objects
+//!
+//! A multimethod holds implementations registered for tuples of classes.
+//! A call picks the most specific implementation applicable to the dynamic types of its arguments:
+//! one whose classes are, argument by argument, subclasses of (or the same as) those of every other applicable implementation.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use dynamic_object::multimethod::Multimethod2;
+//! #[subclass(DynamicObjectBase)]
+//! struct Shape;
+//!
+//! #[subclass(Shape, shape)]
+//! struct Circle {
+//!       shape: Shape,
+//!       radius: f32
+//! }
+//!
+//! let mut collide = Multimethod2::<Shape, Shape, &'static str>::new();
+//! collide.register(|_: &Shape, _: &Shape| "shape/shape");
+//! collide.register(|_: &Circle, _: &Shape| "circle/shape");
+//!
+//! let circle = Object::<Circle>::new(Box::new(Circle { shape: Shape, radius: 1.0 })).cast::<Shape>();
+//! let shape = Object::<Shape>::new(Box::new(Shape));
+//! assert!(collide.call(&circle, &shape) == Ok("circle/shape"));
+//! assert!(collide.call(&shape, &circle) == Ok("shape/shape"));
+//! ```
+use core::{cell::RefCell, fmt, ops::Deref};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+use crate::{castOffset, isSubclassOf, Class, Object};
+
+/// Why a multimethod call could not be resolved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DispatchError {
+      /// No implementation accepts the dynamic types of the arguments
+      NotFound,
+      /// Several implementations apply and none is more specific than the others
+      Ambiguous,
+}
+
+impl fmt::Display for DispatchError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  DispatchError::NotFound => write!(f, "no implementation matches the arguments"),
+                  DispatchError::Ambiguous => write!(f, "ambiguous call: several implementations match the arguments"),
+            }
+      }
+}
+
+/// Classes an implementation was registered for
+struct Signature<const N: usize> {
+      ids: [usize; N],
+      isa: [fn(usize) -> bool; N],
+}
+
+impl<const N: usize> Signature<N> {
+      /// Each class of 'self' is a subclass of (or the same as) the matching class of 'other'
+      fn isMoreSpecific(&self, other: &Self) -> bool {
+            (0..N).all(|i| (self.isa[i])(other.ids[i]))
+      }
+}
+
+/// Resolution shared by all arities
+struct Dispatcher<F: ?Sized, const N: usize> {
+      entries: Vec<(Signature<N>, Box<F>)>,
+      cache: RefCell<BTreeMap<[usize; N], Result<usize, DispatchError>>>,
+}
+
+impl<F: ?Sized, const N: usize> Dispatcher<F, N> {
+      fn new() -> Self {
+            Self {
+                  entries: Vec::new(),
+                  cache: RefCell::new(BTreeMap::new()),
+            }
+      }
+
+      fn insert(&mut self, signature: Signature<N>, implementation: Box<F>) {
+            self.cache.borrow_mut().clear();
+            match self.entries.iter().position(|(entry, _)| entry.ids == signature.ids) {
+                  Some(i) => self.entries[i] = (signature, implementation),
+                  None => self.entries.push((signature, implementation)),
+            }
+      }
+
+      /// Finds the implementation for arguments whose dynamic types are 'ids' and pass 'isa'
+      fn resolve(&self, ids: [usize; N], isa: [fn(usize) -> bool; N]) -> Result<&F, DispatchError> {
+            let cached = self.cache.borrow().get(&ids).copied();
+            let index = match cached {
+                  Some(index) => index,
+                  None => {
+                        let index = self.select(isa);
+                        self.cache.borrow_mut().insert(ids, index);
+                        index
+                  }
+            };
+            index.map(|i| &*self.entries[i].1)
+      }
+
+      fn select(&self, isa: [fn(usize) -> bool; N]) -> Result<usize, DispatchError> {
+            let applicable: Vec<usize> = (0..self.entries.len())
+                  .filter(|&i| (0..N).all(|arg| isa[arg](self.entries[i].0.ids[arg])))
+                  .collect();
+            let mut best = applicable.iter().copied().filter(|&i| {
+                  applicable.iter().all(|&j| i == j || !self.entries[j].0.isMoreSpecific(&self.entries[i].0))
+            });
+            match (best.next(), best.next()) {
+                  (Some(i), None) => Ok(i),
+                  (Some(_), Some(_)) => Err(DispatchError::Ambiguous),
+                  (None, _) => Err(DispatchError::NotFound),
+            }
+      }
+}
+
+/// Reinterpret a reference to 'Base' as the 'Derived' object it is part of
+///
+/// # Safety
+/// 'object' must be part of a 'Derived'
+unsafe fn downcast<Base: Class, Derived: Class>(object: &Base) -> &Derived {
+      let inner = object as *const Base as usize;
+      let inner = inner.wrapping_add(castOffset::<Base, Derived>() as usize);
+      &*(inner as *const Derived)
+}
+
+macro_rules! multimethod {
+      ($(#[$doc:meta])* $name:ident, $n:literal, $($base:ident $class:ident $container:ident $arg:ident),+) => {
+            $(#[$doc])*
+            pub struct $name<$($base: Class,)+ R> {
+                  dispatcher: Dispatcher<dyn Fn($(&$base),+) -> R, $n>,
+            }
+
+            impl<$($base: Class,)+ R> $name<$($base,)+ R> {
+                  pub fn new() -> Self {
+                        Self {
+                              dispatcher: Dispatcher::new(),
+                        }
+                  }
+
+                  /// Register the implementation for these argument classes, replacing any previous one
+                  /// panic if a class does not inherit from the matching base class
+                  pub fn register<$($class: Class),+>(&mut self, implementation: impl Fn($(&$class),+) -> R + 'static) {
+                        $(assert!(isSubclassOf::<$class, $base>(), "{} does not inherit from {}", core::any::type_name::<$class>(), core::any::type_name::<$base>());)+
+                        let signature = Signature {
+                              ids: [$($class::id()),+],
+                              isa: [$($class::isa),+],
+                        };
+                        // Only called once resolution checked the dynamic type of each argument is a '$class'
+                        let implementation = move |$($arg: &$base),+| implementation($(unsafe { downcast::<$base, $class>($arg) }),+);
+                        self.dispatcher.insert(signature, Box::new(implementation));
+                  }
+
+                  /// Call the most specific implementation for the dynamic types of the arguments
+                  pub fn call<$($container: Deref),+>(&self, $($arg: &Object<$base, $container>),+) -> Result<R, DispatchError> {
+                        let implementation = self.dispatcher.resolve(
+                              [$($arg.dynamic_id()),+],
+                              [$($arg.class.isa),+]
+                        )?;
+                        Ok(implementation($(&**$arg),+))
+                  }
+            }
+
+            impl<$($base: Class,)+ R> Default for $name<$($base,)+ R> {
+                  fn default() -> Self {
+                        Self::new()
+                  }
+            }
+      };
+}
+
+multimethod!(
+      /// Multimethod dispatching on the dynamic types of two objects
+      Multimethod2, 2, A X CA a, B Y CB b
+);
+multimethod!(
+      /// Multimethod dispatching on the dynamic types of three objects
+      Multimethod3, 3, A X CA a, B Y CB b, C Z CC c
+);
+multimethod!(
+      /// Multimethod dispatching on the dynamic types of four objects
+      Multimethod4, 4, A X CA a, B Y CB b, C Z CC c, D W CD d
+);
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase)]
+      struct Shape {
+            id: u32
+      }
+
+      #[subclass(Shape, shape)]
+      #[repr(C)]
+      struct Circle {
+            radius: f32,
+            shape: Shape
+      }
+
+      #[subclass(Shape, shape)]
+      struct Square {
+            side: f32,
+            shape: Shape
+      }
+
+      // Neither parent is the first field
+      #[subclass(Circle, circle)]
+      #[repr(C)]
+      struct Ring {
+            width: f32,
+            circle: Circle
+      }
+
+      fn circle() -> Object<Shape> {
+            Object::<Circle>::new(Box::new(Circle { radius: 2.0, shape: Shape { id: 1 } })).cast::<Shape>()
+      }
+
+      fn square() -> Object<Shape> {
+            Object::<Square>::new(Box::new(Square { side: 3.0, shape: Shape { id: 2 } })).cast::<Shape>()
+      }
+
+      #[test]
+      fn most_specific() {
+            let mut collide = Multimethod2::<Shape, Shape, f32>::new();
+            collide.register(|_: &Shape, _: &Shape| 0.0);
+            collide.register(|a: &Circle, b: &Square| a.radius + b.side);
+            collide.register(|a: &Circle, _: &Shape| a.radius);
+
+            assert!(collide.call(&circle(), &square()) == Ok(5.0));
+            assert!(collide.call(&circle(), &circle()) == Ok(2.0));
+            assert!(collide.call(&square(), &circle()) == Ok(0.0));
+            // Resolved from the cache
+            assert!(collide.call(&circle(), &square()) == Ok(5.0));
+      }
+
+      #[test]
+      fn errors() {
+            let mut collide = Multimethod2::<Shape, Shape, u32>::new();
+            collide.register(|a: &Circle, _: &Shape| a.shape.id);
+            collide.register(|_: &Shape, b: &Circle| b.shape.id);
+
+            assert!(collide.call(&circle(), &circle()) == Err(DispatchError::Ambiguous));
+            assert!(collide.call(&square(), &square()) == Err(DispatchError::NotFound));
+
+            collide.register(|a: &Circle, b: &Circle| a.shape.id + b.shape.id);
+            assert!(collide.call(&circle(), &circle()) == Ok(2));
+      }
+
+      #[test]
+      fn three_arguments() {
+            let mut combine = Multimethod3::<Shape, Shape, Shape, u32>::new();
+            combine.register(|_: &Shape, _: &Shape, _: &Shape| 0);
+            combine.register(|_: &Shape, b: &Square, _: &Circle| b.shape.id);
+            assert!(combine.call(&circle(), &square(), &circle()) == Ok(2));
+            assert!(combine.call(&circle(), &circle(), &circle()) == Ok(0));
+      }
+
+      #[test]
+      fn grandchild() {
+            let ring = || Object::<Ring>::new(Box::new(Ring { width: 0.5, circle: Circle { radius: 2.0, shape: Shape { id: 3 } } }));
+            let circle = || Object::<Circle>::new(Box::new(Circle { radius: 1.0, shape: Shape { id: 4 } }));
+
+            let mut measure = Multimethod2::<Shape, Shape, f32>::new();
+            measure.register(|_: &Shape, _: &Shape| 0.0);
+            measure.register(|a: &Ring, b: &Circle| a.width + a.circle.radius + b.radius);
+            assert!(measure.call(&ring().cast::<Shape>(), &circle().cast::<Shape>()) == Ok(3.5));
+
+            // Base classes which are not the root
+            let mut measure = Multimethod2::<Circle, Circle, f32>::new();
+            measure.register(|a: &Circle, b: &Circle| a.radius + b.radius);
+            measure.register(|a: &Ring, b: &Circle| a.width + a.circle.shape.id as f32 + b.radius);
+            assert!(measure.call(&ring().cast::<Circle>(), &circle()) == Ok(4.5));
+            assert!(measure.call(&circle(), &ring().cast::<Circle>()) == Ok(3.0));
+      }
+}
@@ -0,0 +1,430 @@
+//! Field reflection
+//!
+//! #[subclass] records the name, type, offset and size of every field of the struct.
+//! `#[subclass(.., any)]` adds a getter and a setter working through `&dyn Any`, which requires the types
+//! of the fields to be 'static: without it, accessing the fields through `Any` fails with [`FieldError::Unsupported`].
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase, any)]
+//! struct Class {
+//!       value: u32,
+//!       foo: u32
+//! }
+//!
+//! #[subclass(Class, parent, any)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let object = Object::<Derived>::new(Box::new(Derived { field: 1, parent: Class { value: 2, foo: 3 } }));
+//! let object = object.cast::<Class>();
+//!
+//! // Fields of the dynamic type first, then inherited ones
+//! let names: Vec<_> = object.fields().map(|field| field.name()).collect();
+//! assert!(names == ["field", "parent", "value", "foo"]);
+//!
+//! let value = object.fields().find(|field| field.name() == "value").unwrap();
+//! assert!(object.field_ref(&value).unwrap().downcast_ref::<u32>() == Some(&2));
+//!
+//! // Paths start at the dynamic type and go through parent fields
+//! let mut object = object;
+//! assert!(object.get_path::<u32>("parent.value") == Ok(&2));
+//! object.set_path("parent.foo", 4u32).unwrap();
+//! // Flattened access finds inherited fields by name
+//! assert!(object.get_field::<u32>("foo") == Ok(&4));
+//! ```
+use core::{any::Any, fmt, ops::{Deref, DerefMut}};
+use alloc::boxed::Box;
+
+use crate::{format::FormatFn, value::Value, Class, ClassInfo, Object};
+
+/// Gets the field from the struct it is part of, None if the struct is not of the right class or has no `any` option
+pub type Getter = fn(object: &dyn Any) -> Option<&dyn Any>;
+/// Mutable version of [`Getter`]
+pub type GetterMut = fn(object: &mut dyn Any) -> Option<&mut dyn Any>;
+/// Sets the field of the struct it is part of, giving the value back if the struct or the value is not of the right type
+pub type Setter = fn(object: &mut dyn Any, value: Box<dyn Any>) -> Result<(), Box<dyn Any>>;
+
+/// A field of a #[subclass] struct
+pub struct FieldInfo {
+      #[doc(hidden)]
+      pub name: &'static str,
+      #[doc(hidden)]
+      pub type_name: fn() -> &'static str,
+      #[doc(hidden)]
+      pub offset: usize,
+      #[doc(hidden)]
+      pub size: usize,
+      /// Debug of the field at an address, None if its type does not implement it
+      #[doc(hidden)]
+      pub debug: FormatFn,
+      /// Conversions of the field at an address to and from Value, None and false if its type does not support them
+      #[doc(hidden)]
+      pub to_value: unsafe fn(field: *const u8) -> Option<Value>,
+      #[doc(hidden)]
+      pub set_value: unsafe fn(field: *mut u8, value: &Value) -> bool,
+      /// Whether `set_value` would accept 'value'
+      #[doc(hidden)]
+      pub accepts_value: fn(value: &Value) -> bool,
+      /// Equality of the fields at two addresses, None if their type does not implement PartialEq
+      #[doc(hidden)]
+      pub equals: unsafe fn(a: *const u8, b: *const u8) -> Option<bool>,
+      #[doc(hidden)]
+      pub parent: Option<fn() -> &'static ClassInfo>,
+      #[doc(hidden)]
+      pub get: Getter,
+      #[doc(hidden)]
+      pub get_mut: GetterMut,
+      #[doc(hidden)]
+      pub set: Setter,
+}
+
+impl FieldInfo {
+      /// Name of the field, or its index for tuple structs
+      pub fn name(&self) -> &'static str {
+            self.name
+      }
+
+      /// Name of the type of the field
+      pub fn type_name(&self) -> &'static str {
+            (self.type_name)()
+      }
+
+      /// Offset of the field inside its struct
+      pub fn offset(&self) -> usize {
+            self.offset
+      }
+
+      pub fn size(&self) -> usize {
+            self.size
+      }
+
+      /// Class of the parent if this field holds it
+      pub fn parent_class(&self) -> Option<&'static ClassInfo> {
+            self.parent.map(|parent| parent())
+      }
+
+      /// Borrow the field of 'object'
+      pub fn get<'a>(&self, object: &'a dyn Any) -> Option<&'a dyn Any> {
+            (self.get)(object)
+      }
+
+      /// Mutably borrow the field of 'object'
+      pub fn get_mut<'a>(&self, object: &'a mut dyn Any) -> Option<&'a mut dyn Any> {
+            (self.get_mut)(object)
+      }
+
+      /// Replace the field of 'object', giving 'value' back if 'object' or 'value' has the wrong type
+      pub fn set(&self, object: &mut dyn Any, value: Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+            (self.set)(object, value)
+      }
+}
+
+impl ClassInfo {
+      /// Fields declared by the class itself, in declaration order
+      pub fn fields(&self) -> &'static [FieldInfo] {
+            self.fields
+      }
+}
+
+/// A field of an object, found by [`Object::fields`]
+#[derive(Clone, Copy)]
+pub struct ObjectField {
+      // Dynamic type of the object the field was found in
+      owner: &'static ClassInfo,
+      class: &'static ClassInfo,
+      // Offset of 'class' inside the object
+      classOffset: usize,
+      field: &'static FieldInfo,
+}
+
+impl ObjectField {
+      pub fn name(&self) -> &'static str {
+            self.field.name
+      }
+
+      /// The class declaring the field
+      pub fn class(&self) -> &'static ClassInfo {
+            self.class
+      }
+
+      pub fn info(&self) -> &'static FieldInfo {
+            self.field
+      }
+
+      /// Offset of the field from the start of the object created
+      pub fn offset(&self) -> usize {
+            self.classOffset + self.field.offset
+      }
+
+      /// Offset of the struct declaring the field from the start of the object created
+      pub fn class_offset(&self) -> usize {
+            self.classOffset
+      }
+}
+
+/// Why a field could not be accessed by name or path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldError {
+      /// No field has this name
+      NotFound,
+      /// A segment of the path before the last one is not a parent field
+      NotParent,
+      /// The field is not of the requested type
+      WrongType,
+      /// The class declaring the field does not have the `any` option
+      Unsupported,
+}
+
+impl fmt::Display for FieldError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  FieldError::NotFound => write!(f, "no such field"),
+                  FieldError::NotParent => write!(f, "field does not hold a parent class"),
+                  FieldError::WrongType => write!(f, "field has another type"),
+                  FieldError::Unsupported => write!(f, "class of the field does not have the any option"),
+            }
+      }
+}
+
+/// Every class of the chain of 'class' with its offset from the start of a 'class' object
+pub(crate) fn levels(class: &'static ClassInfo) -> impl Iterator<Item = (&'static ClassInfo, usize)> {
+      core::iter::successors(Some((class, 0)), |(class, offset)| {
+            class.parent().map(|parent| (parent, offset + class.parent_offset() as usize))
+      })
+}
+
+/// 'field' if its class has the `any` option
+fn anyField(field: ObjectField) -> Result<ObjectField, FieldError> {
+      match field.class.as_any {
+            Some(_) => Ok(field),
+            None => Err(FieldError::Unsupported),
+      }
+}
+
+impl<T: Class, C: Deref> Object<T, C> {
+      /// Fields of the dynamic type of the object followed by the inherited ones, up to the root class
+      pub fn fields(&self) -> impl Iterator<Item = ObjectField> {
+            let owner = self.class;
+            levels(owner).flat_map(move |(class, classOffset)| {
+                  class.fields.iter().map(move |field| ObjectField {
+                        owner,
+                        class,
+                        classOffset,
+                        field,
+                  })
+            })
+      }
+
+      /// Borrow a field found by `fields()`
+      /// None if it was found in an object of another class or its class does not have the `any` option
+      pub fn field_ref(&self, field: &ObjectField) -> Option<&dyn Any> {
+            if field.owner != self.class {
+                  return None
+            }
+            let class = self.base().wrapping_add(field.classOffset);
+            field.field.get(unsafe { &*(field.class.as_any?)(class) })
+      }
+
+      /// The field at a dotted path such as "parent.value", starting from the dynamic type of the object
+      /// Every segment but the last must name the field holding the parent
+      pub fn field_at(&self, path: &str) -> Result<ObjectField, FieldError> {
+            let mut class = self.class;
+            let mut classOffset = 0;
+            let mut segments = path.split('.').peekable();
+            while let Some(segment) = segments.next() {
+                  let field = class.fields.iter().find(|field| field.name == segment).ok_or(FieldError::NotFound)?;
+                  if segments.peek().is_none() {
+                        return Ok(ObjectField {
+                              owner: self.class,
+                              class,
+                              classOffset,
+                              field,
+                        });
+                  }
+                  class = field.parent_class().ok_or(FieldError::NotParent)?;
+                  classOffset += field.offset;
+            }
+            Err(FieldError::NotFound)
+      }
+
+      /// The field called 'name' in the dynamic type of the object or the nearest ancestor declaring one
+      pub fn find_field(&self, name: &str) -> Result<ObjectField, FieldError> {
+            self.fields().find(|field| field.name() == name).ok_or(FieldError::NotFound)
+      }
+
+      /// Borrow the field at 'path', see `field_at`
+      pub fn get_path<V: Any>(&self, path: &str) -> Result<&V, FieldError> {
+            let field = anyField(self.field_at(path)?)?;
+            self.field_ref(&field).and_then(|value| value.downcast_ref()).ok_or(FieldError::WrongType)
+      }
+
+      /// Borrow the field called 'name', inherited or not, see `find_field`
+      pub fn get_field<V: Any>(&self, name: &str) -> Result<&V, FieldError> {
+            let field = anyField(self.find_field(name)?)?;
+            self.field_ref(&field).and_then(|value| value.downcast_ref()).ok_or(FieldError::WrongType)
+      }
+}
+
+impl<T: Class, C: DerefMut> Object<T, C> {
+      /// Mutably borrow a field found by `fields()`
+      /// None if it was found in an object of another class or its class does not have the `any` option
+      pub fn field_mut(&mut self, field: &ObjectField) -> Option<&mut dyn Any> {
+            if field.owner != self.class {
+                  return None
+            }
+            let class = self.baseMut().wrapping_add(field.classOffset);
+            field.field.get_mut(unsafe { &mut *(field.class.as_any_mut?)(class) })
+      }
+
+      /// Replace a field found by `fields()`, giving 'value' back if it has the wrong type,
+      /// if the field was found in an object of another class or if its class does not have the `any` option
+      pub fn set_field(&mut self, field: &ObjectField, value: Box<dyn Any>) -> Result<(), Box<dyn Any>> {
+            let asAny = match field.class.as_any_mut {
+                  Some(asAny) if field.owner == self.class => asAny,
+                  _ => return Err(value),
+            };
+            let class = self.baseMut().wrapping_add(field.classOffset);
+            field.field.set(unsafe { &mut *asAny(class) }, value)
+      }
+
+      /// Mutably borrow the field at 'path', see `field_at`
+      pub fn get_path_mut<V: Any>(&mut self, path: &str) -> Result<&mut V, FieldError> {
+            let field = anyField(self.field_at(path)?)?;
+            self.field_mut(&field).and_then(|value| value.downcast_mut()).ok_or(FieldError::WrongType)
+      }
+
+      /// Replace the field at 'path', see `field_at`
+      pub fn set_path<V: Any>(&mut self, path: &str, value: V) -> Result<(), FieldError> {
+            *self.get_path_mut(path)? = value;
+            Ok(())
+      }
+
+      /// Mutably borrow the field called 'name', inherited or not, see `find_field`
+      pub fn get_field_mut<V: Any>(&mut self, name: &str) -> Result<&mut V, FieldError> {
+            let field = anyField(self.find_field(name)?)?;
+            self.field_mut(&field).and_then(|value| value.downcast_mut()).ok_or(FieldError::WrongType)
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, Class, DynamicObjectBase, Object};
+      use super::FieldError;
+
+      #[subclass(DynamicObjectBase, any)]
+      struct Entity {
+            id: u64,
+            name: String
+      }
+
+      #[subclass(Entity, entity, any)]
+      struct Player {
+            health: u8,
+            entity: Entity,
+            score: u32
+      }
+
+      #[subclass(DynamicObjectBase)]
+      struct Point(f32, f32);
+
+      // Metadata only
+      #[subclass(Player, player)]
+      struct Npc {
+            player: Player,
+            route: Vec<u32>
+      }
+
+      fn player() -> Object<Entity> {
+            Object::<Player>::new(Box::new(Player {
+                  health: 100,
+                  entity: Entity { id: 7, name: "seven".into() },
+                  score: 12
+            })).cast::<Entity>()
+      }
+
+      #[test]
+      fn metadata() {
+            let fields = Player::info().fields();
+            assert!(fields.len() == 3);
+            assert!(fields[1].name() == "entity");
+            assert!(fields[1].offset() == core::mem::offset_of!(Player, entity));
+            assert!(fields[1].size() == core::mem::size_of::<Entity>());
+            assert!(fields[1].parent_class() == Some(Entity::info()));
+            assert!(fields[0].parent_class().is_none());
+            assert!(Entity::info().fields()[1].type_name() == core::any::type_name::<String>());
+
+            let names: Vec<_> = Point::info().fields().iter().map(|field| field.name()).collect();
+            assert!(names == ["0", "1"]);
+      }
+
+      #[test]
+      fn get_set() {
+            let mut player = Player { health: 1, entity: Entity { id: 2, name: String::new() }, score: 3 };
+            let score = &Player::info().fields()[2];
+            assert!(score.get(&player).unwrap().downcast_ref::<u32>() == Some(&3));
+            assert!(score.set(&mut player, Box::new(4u32)).is_ok());
+            assert!(player.score == 4);
+            assert!(score.set(&mut player, Box::new(5u8)).is_err());
+            assert!(score.get(&player.entity).is_none());
+      }
+
+      #[test]
+      fn object_fields() {
+            let mut object = player();
+            let fields: Vec<_> = object.fields().collect();
+            let names: Vec<_> = fields.iter().map(|field| field.name()).collect();
+            assert!(names == ["health", "entity", "score", "id", "name"]);
+
+            let id = fields[3];
+            assert!(id.class() == Entity::info());
+            assert!(id.offset() == core::mem::offset_of!(Player, entity) + core::mem::offset_of!(Entity, id));
+            assert!(object.field_ref(&id).unwrap().downcast_ref::<u64>() == Some(&7));
+
+            *object.field_mut(&fields[0]).unwrap().downcast_mut::<u8>().unwrap() = 50;
+            assert!(object.set_field(&fields[4], Box::new(String::from("renamed"))).is_ok());
+            assert!(object.name == "renamed");
+            assert!(object.cast::<Player>().health == 50);
+
+            let other = Object::<Entity>::new(Box::new(Entity { id: 0, name: String::new() }));
+            assert!(other.field_ref(&id).is_none());
+      }
+
+      #[test]
+      fn paths() {
+            let mut object = player();
+            assert!(object.get_path::<u64>("entity.id") == Ok(&7));
+            assert!(object.field_at("entity.id").unwrap().offset() == object.find_field("id").unwrap().offset());
+            object.set_path("entity.name", String::from("eight")).unwrap();
+            *object.get_field_mut::<u32>("score").unwrap() += 1;
+            assert!(object.get_field::<String>("name").map(String::as_str) == Ok("eight"));
+            assert!(object.get_path::<u32>("score") == Ok(&13));
+
+            assert!(object.get_path::<u64>("id") == Err(FieldError::NotFound));
+            assert!(object.get_path::<u64>("entity.missing") == Err(FieldError::NotFound));
+            assert!(object.get_path::<u64>("health.id") == Err(FieldError::NotParent));
+            assert!(object.set_path("entity.id", 1u32) == Err(FieldError::WrongType));
+            assert!(object.get_field::<u8>("missing") == Err(FieldError::NotFound));
+      }
+
+      #[test]
+      fn without_any() {
+            let player = Player { health: 1, entity: Entity { id: 2, name: String::new() }, score: 3 };
+            let npc = Npc { player, route: vec![1] };
+            let route = &Npc::info().fields()[1];
+            assert!(route.name() == "route" && route.get(&npc).is_none());
+            let mut object = Object::<Npc>::new(Box::new(npc)).cast::<Entity>();
+            assert!(object.get_path::<Vec<u32>>("route") == Err(FieldError::Unsupported));
+            assert!(object.set_field(&object.find_field("route").unwrap(), Box::new(vec![2u32])).is_err());
+            // Inherited fields of classes with the option still work
+            assert!(object.get_path::<u8>("player.health") == Ok(&1));
+            assert!(object.get_field::<u64>("id") == Ok(&2));
+      }
+}
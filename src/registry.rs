@@ -0,0 +1,310 @@
+//! Runtime registry of every class
+//!
+//! Each `impl Class` generated by #[subclass] registers its [`ClassInfo`] at link time,
+//! so classes of any crate linked in the program can be listed without running any code first.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase)]
+//! struct Light;
+//!
+//! #[subclass(Light, light)]
+//! struct SpotLight {
+//!       light: Light
+//! }
+//!
+//! let spot = registry::by_name("SpotLight").unwrap();
+//! assert!(spot.parent().unwrap().name() == "Light");
+//! assert!(registry::subclasses_of::<Light>().any(|class| class.name() == "SpotLight"));
+//! ```
+use core::{any::Any, fmt};
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{binary::{DecodeFn, Encode}, compare::{CmpFn, EqFn, HashFn}, format::FormatFn, invariant::Invariant, layout, reflect::FieldInfo, Class, Dyn, DynamicObjectBase, Object};
+
+/// Clones the most-derived struct at 'object'
+pub type CloneFn = unsafe fn(object: *const u8) -> Box<dyn Dyn>;
+
+/// Creates an object from constructor arguments, None if they have the wrong type
+pub type Constructor = fn(args: &dyn Any) -> Option<Object<DynamicObjectBase>>;
+
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static CLASSES: [&'static ClassInfo];
+
+/// Runtime information about a class
+///
+/// Generated by #[subclass]: use `T::info()` or [`Object::class`](crate::Object::class) to get one
+pub struct ClassInfo {
+      #[doc(hidden)]
+      pub name: &'static str,
+      #[doc(hidden)]
+      pub path: &'static str,
+      #[doc(hidden)]
+      pub id: fn() -> usize,
+      #[doc(hidden)]
+      pub isa: fn(id: usize) -> bool,
+      #[doc(hidden)]
+      pub parent: fn() -> &'static ClassInfo,
+      #[doc(hidden)]
+      pub offset: fn() -> isize,
+      #[doc(hidden)]
+      pub fields: &'static [FieldInfo],
+      #[doc(hidden)]
+      pub layout_hash: u64,
+      /// None without the `any` option
+      #[doc(hidden)]
+      pub as_any: Option<fn(object: *const u8) -> *const dyn Any>,
+      #[doc(hidden)]
+      pub as_any_mut: Option<fn(object: *mut u8) -> *mut dyn Any>,
+      /// Set by #[invariant]
+      #[doc(hidden)]
+      pub invariants: &'static [Invariant],
+      /// Set by #[constructible]
+      #[doc(hidden)]
+      pub construct: Option<Constructor>,
+      /// Set by the `clone` option
+      #[doc(hidden)]
+      pub clone: Option<CloneFn>,
+      /// Debug and Display of the class, returning None if it does not implement them
+      #[doc(hidden)]
+      pub debug: FormatFn,
+      #[doc(hidden)]
+      pub display: FormatFn,
+      /// Set by the `lifecycle` option
+      #[doc(hidden)]
+      pub on_init: Option<unsafe fn(object: *mut u8)>,
+      #[doc(hidden)]
+      pub on_destroy: Option<unsafe fn(object: *mut u8)>,
+      /// Set by the `eq`, `hash` and `ord` options
+      #[doc(hidden)]
+      pub eq: Option<EqFn>,
+      #[doc(hidden)]
+      pub hash: Option<HashFn>,
+      #[doc(hidden)]
+      pub cmp: Option<CmpFn>,
+      /// Set by the `version` option
+      #[doc(hidden)]
+      pub version: Option<u32>,
+      /// Set by the `binary` option
+      #[doc(hidden)]
+      pub encode: Option<fn(object: *const u8) -> *const dyn Encode>,
+      #[doc(hidden)]
+      pub decode: Option<DecodeFn>,
+      /// binary::Decode::SCHEMA of the class
+      #[doc(hidden)]
+      pub schema: u64,
+}
+
+impl ClassInfo {
+      /// Information of DynamicObjectBase
+      /// Fields a class does not set are taken from it
+      #[doc(hidden)]
+      pub const ROOT: ClassInfo = ClassInfo {
+            name: "DynamicObjectBase",
+            path: "dynamic_object::DynamicObjectBase",
+            id: DynamicObjectBase::id,
+            isa: DynamicObjectBase::isa,
+            parent: DynamicObjectBase::info,
+            offset: DynamicObjectBase::offset,
+            fields: &[],
+            layout_hash: layout::ROOT,
+            as_any: Some(|object| object as *const DynamicObjectBase as *const dyn Any),
+            as_any_mut: Some(|object| object as *mut DynamicObjectBase as *mut dyn Any),
+            invariants: &[],
+            construct: None,
+            clone: None,
+            debug: |_, _| None,
+            display: |_, _| None,
+            on_init: None,
+            on_destroy: None,
+            eq: None,
+            hash: None,
+            cmp: None,
+            version: None,
+            encode: None,
+            decode: None,
+            schema: 0,
+      };
+
+      /// Name of the struct
+      pub fn name(&self) -> &'static str {
+            self.name
+      }
+
+      /// Name of the struct prefixed by its module path
+      pub fn path(&self) -> &'static str {
+            self.path
+      }
+
+      /// Same as `Class::id()`
+      pub fn id(&self) -> usize {
+            (self.id)()
+      }
+
+      /// The class it inherits from, None for DynamicObjectBase
+      pub fn parent(&self) -> Option<&'static ClassInfo> {
+            let parent = (self.parent)();
+            if parent.id() == self.id() {
+                  None
+            } else {
+                  Some(parent)
+            }
+      }
+
+      /// Offset of the parent inside an instance of this class
+      pub fn parent_offset(&self) -> isize {
+            (self.offset)()
+      }
+
+      /// Check if this class is 'other' or inherits from it
+      pub fn is_subclass_of(&self, other: &ClassInfo) -> bool {
+            (self.isa)(other.id())
+      }
+
+      /// Same as `Class::LAYOUT_HASH`
+      pub fn layout_hash(&self) -> u64 {
+            self.layout_hash
+      }
+
+      /// Schema version set by `#[subclass(.., version = N)]`, None if the class has none
+      pub fn version(&self) -> Option<u32> {
+            self.version
+      }
+
+      /// This class followed by its parent, its parent's parent, up to DynamicObjectBase
+      pub fn ancestors(&'static self) -> impl Iterator<Item = &'static ClassInfo> {
+            core::iter::successors(Some(self), |class| class.parent())
+      }
+}
+
+impl PartialEq for ClassInfo {
+      fn eq(&self, other: &Self) -> bool {
+            self.id() == other.id()
+      }
+}
+
+impl Eq for ClassInfo {
+
+}
+
+impl fmt::Debug for ClassInfo {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.path)
+      }
+}
+
+/// Every registered class
+pub fn all() -> impl Iterator<Item = &'static ClassInfo> {
+      CLASSES.iter().copied()
+}
+
+/// Every class inheriting from 'T', directly or not, 'T' excluded
+pub fn subclasses_of<T: Class>() -> impl Iterator<Item = &'static ClassInfo> {
+      all().filter(|class| class.id() != T::id() && class.is_subclass_of(T::info()))
+}
+
+/// Why [`by_name`] found no class
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LookupError {
+      /// No class has this name or path
+      UnknownClass,
+      /// Several classes have this name, the paths of which are listed
+      Ambiguous(Vec<&'static str>),
+}
+
+impl fmt::Display for LookupError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  LookupError::UnknownClass => write!(f, "unknown class"),
+                  LookupError::Ambiguous(paths) => write!(f, "ambiguous class name, use one of the paths {}", paths.join(", ")),
+            }
+      }
+}
+
+/// Find a class by path, or by name if no other class has that name
+pub fn by_name(name: &str) -> Result<&'static ClassInfo, LookupError> {
+      if let Some(class) = all().find(|class| class.path == name) {
+            return Ok(class);
+      }
+      let named: Vec<_> = all().filter(|class| class.name == name).collect();
+      match named.as_slice() {
+            [] => Err(LookupError::UnknownClass),
+            [class] => Ok(class),
+            _ => Err(LookupError::Ambiguous(named.iter().map(|class| class.path).collect())),
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, Class, DynamicObjectBase, Object};
+      use super::*;
+
+      #[subclass(DynamicObjectBase)]
+      struct Plugin;
+
+      #[subclass(Plugin, plugin)]
+      struct Exporter {
+            plugin: Plugin
+      }
+
+      #[subclass(Exporter, exporter)]
+      struct PngExporter {
+            exporter: Exporter
+      }
+
+      mod first {
+            use super::*;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Twin;
+      }
+
+      mod second {
+            use super::*;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Twin;
+      }
+
+      #[test]
+      fn enumerate() {
+            assert!(all().any(|class| class == Exporter::info()));
+            assert!(all().any(|class| class == DynamicObjectBase::info()));
+
+            let mut subclasses: Vec<_> = subclasses_of::<Plugin>().map(ClassInfo::name).collect();
+            subclasses.sort();
+            assert!(subclasses == ["Exporter", "PngExporter"]);
+      }
+
+      #[test]
+      fn lookup() {
+            let png = by_name("PngExporter").unwrap();
+            assert!(png == PngExporter::info());
+            assert!(by_name(png.path()) == Ok(png));
+            assert!(by_name("Missing") == Err(LookupError::UnknownClass));
+
+            // Ambiguous names need the path
+            let Err(LookupError::Ambiguous(mut paths)) = by_name("Twin") else { unreachable!() };
+            paths.sort();
+            assert!(paths == [first::Twin::info().path(), second::Twin::info().path()]);
+            assert!(by_name(first::Twin::info().path()) == Ok(first::Twin::info()));
+            assert!(by_name(second::Twin::info().path()) == Ok(second::Twin::info()));
+
+            let chain: Vec<_> = png.ancestors().map(ClassInfo::name).collect();
+            assert!(chain == ["PngExporter", "Exporter", "Plugin", "DynamicObjectBase"]);
+            assert!(DynamicObjectBase::info().parent().is_none());
+      }
+
+      #[test]
+      fn object_class() {
+            let object = Object::<PngExporter>::new(Box::new(PngExporter { exporter: Exporter { plugin: Plugin } }));
+            let object = object.cast::<Plugin>();
+            assert!(object.class() == PngExporter::info());
+      }
+}
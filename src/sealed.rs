@@ -0,0 +1,150 @@
+//! Closed class hierarchies
+//!
+//! `#[sealed_hierarchy]` is put on an inline module holding a root class and all of its subclasses.
+//! It generates an enum with one variant per class so matching on the dynamic type is exhaustive:
+//! adding a subclass to the module makes every non-exhaustive `match` fail to compile.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[sealed_hierarchy]
+//! mod shapes {
+//!       use dynamic_object::*;
+//!
+//!       #[subclass(DynamicObjectBase)]
+//!       pub struct Shape {
+//!             pub id: u32
+//!       }
+//!
+//!       #[subclass(Shape, shape)]
+//!       pub struct Circle {
+//!             pub shape: Shape,
+//!             pub radius: f32
+//!       }
+//! }
+//! use shapes::*;
+//!
+//! let object = Object::<Circle>::new(Box::new(Circle { shape: Shape { id: 1 }, radius: 2.0 }));
+//! let object = object.cast::<Shape>();
+//! match object.classify() {
+//!       ShapeView::Circle(circle) => assert!(circle.radius == 2.0),
+//!       ShapeView::Shape(_) => unreachable!()
+//! }
+//! ```
+//!
+//! Classes of the module cannot be inherited from outside of it:
+//! ```compile_fail
+//! # use dynamic_object::*;
+//! #[sealed_hierarchy]
+//! mod shapes {
+//!       use dynamic_object::*;
+//!
+//!       #[subclass(DynamicObjectBase)]
+//!       pub struct Shape {
+//!             pub id: u32
+//!       }
+//! }
+//!
+//! #[subclass(shapes::Shape, shape)]
+//! struct Square {
+//!       shape: shapes::Shape
+//! }
+//! ```
+use core::ops::{Deref, DerefMut};
+
+use crate::{Class, Object};
+
+/// Implemented on the root class by #[sealed_hierarchy]
+pub trait SealedHierarchy: Class + Sized {
+      /// Enum borrowing the object as its dynamic type
+      type View<'a> where Self: 'a;
+      /// Enum mutably borrowing the object as its dynamic type
+      type ViewMut<'a> where Self: 'a;
+
+      fn classify<C: Deref>(object: &Object<Self, C>) -> Self::View<'_>;
+      fn classify_mut<C: DerefMut>(object: &mut Object<Self, C>) -> Self::ViewMut<'_>;
+}
+
+impl<T: SealedHierarchy, C: Deref> Object<T, C> {
+      /// Borrow the object as the variant of its dynamic type
+      pub fn classify(&self) -> T::View<'_> {
+            T::classify(self)
+      }
+}
+
+impl<T: SealedHierarchy, C: DerefMut> Object<T, C> {
+      /// Mutably borrow the object as the variant of its dynamic type
+      pub fn classify_mut(&mut self) -> T::ViewMut<'_> {
+            T::classify_mut(self)
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use crate::{self as dynamic_object, Object};
+
+      #[dynamic_object::sealed_hierarchy]
+      mod nodes {
+            use crate::{self as dynamic_object, DynamicObjectBase};
+            use dynamic_object_derive::subclass;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Node {
+                  pub line: u32
+            }
+
+            #[subclass(Node, node)]
+            pub struct Literal {
+                  pub value: i64,
+                  pub node: Node
+            }
+
+            #[subclass(Node, node)]
+            pub struct Binary {
+                  pub node: Node,
+                  pub op: char
+            }
+
+            #[subclass(Binary, binary)]
+            pub struct Assign {
+                  pub binary: Binary,
+                  pub target: u32
+            }
+      }
+      use nodes::*;
+
+      fn describe(object: &Object<Node>) -> i64 {
+            match object.classify() {
+                  NodeView::Node(node) => node.line as i64,
+                  NodeView::Literal(literal) => literal.value,
+                  NodeView::Binary(binary) => binary.op as i64,
+                  NodeView::Assign(assign) => assign.target as i64 + assign.binary.node.line as i64,
+            }
+      }
+
+      #[test]
+      fn classify() {
+            let node = Object::<Node>::new(Box::new(Node { line: 3 }));
+            let literal = Object::<Literal>::new(Box::new(Literal { value: -7, node: Node { line: 1 } })).cast::<Node>();
+            let assign = Object::<Assign>::new(Box::new(Assign {
+                  binary: Binary { node: Node { line: 10 }, op: '=' },
+                  target: 5
+            })).cast::<Node>();
+
+            assert!(describe(&node) == 3);
+            assert!(describe(&literal) == -7);
+            assert!(describe(&assign) == 15);
+      }
+
+      #[test]
+      fn classify_mut() {
+            let mut object = Object::<Binary>::new(Box::new(Binary { node: Node { line: 0 }, op: '+' })).cast::<Node>();
+            if let NodeViewMut::Binary(binary) = object.classify_mut() {
+                  binary.op = '-';
+            }
+            assert!(object.cast::<Binary>().op == '-');
+      }
+}
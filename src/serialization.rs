@@ -0,0 +1,455 @@
+//! Polymorphic serialization, enabled by the `serde` feature
+//!
+//! Classes opt in with `#[subclass(.., serde)]`, or `#[subclass(.., serde_name = "name")]` to choose
+//! the name written to the data (the path of the struct by default), which must be unique: objects of classes
+//! sharing a name fail to serialize and deserialize. They must also implement
+//! `Serialize` and `Deserialize`. An `Object<Base>` is written as `{ "class": name, "data": value }`
+//! where value is the whole most-derived struct, so inherited fields are kept.
+//! "class" must come before "data" when reading.
+//!
+//! Classes with `#[subclass(.., version = N)]` also write the version of each versioned level
+//! in a "versions" map. When older data is read, every level is migrated from its saved version,
+//! parents first, by `fn migrate_from_vK(old: Value) -> Self` functions of the class, which
+//! receive the data of the class with its parent already migrated.
+//! Levels missing from "versions" are at version 1.
+//!
+//! Unlike [`binary`](crate::binary), no fingerprint of the classes is written nor checked:
+//! changes of the fields are only caught by serde, as missing or unknown fields.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! # use serde::{Serialize, Deserialize};
+//! #[subclass(DynamicObjectBase, serde_name = "shape")]
+//! #[derive(Serialize, Deserialize)]
+//! struct Shape {
+//!       x: f32
+//! }
+//!
+//! #[subclass(Shape, shape, serde_name = "circle")]
+//! #[derive(Serialize, Deserialize)]
+//! struct Circle {
+//!       shape: Shape,
+//!       radius: f32
+//! }
+//!
+//! let object = Object::<Circle>::new(Box::new(Circle { shape: Shape { x: 1.0 }, radius: 2.0 })).cast::<Shape>();
+//! let json = serde_json::to_string(&object).unwrap();
+//! assert!(json == r#"{"class":"circle","data":{"shape":{"x":1.0},"radius":2.0}}"#);
+//!
+//! let object: Object<Shape> = serde_json::from_str(&json).unwrap();
+//! assert!(object.cast::<Circle>().radius == 2.0);
+//! ```
+use core::{fmt, marker::PhantomData, ops::Deref};
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use serde::{de, ser::{self, SerializeStruct}, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{registry::LookupError, value::{self, Value}, Class, ClassInfo, DynamicObjectBase, Object};
+
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static SERIALIZABLE: [SerdeClass];
+
+/// Migrates data saved at version 'from' to the current version of a class, None if there is no migration from 'from'
+pub type Migrate = fn(from: u32, old: Value) -> Result<Option<Value>, value::Error>;
+
+/// Returned by the migrations a class does not define
+#[doc(hidden)]
+pub struct NoMigration;
+
+/// Deserializes the most-derived struct of an object
+pub type DeserializeFn = fn(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Object<DynamicObjectBase>, erased_serde::Error>;
+
+/// A class registered with the `serde` or `serde_name` option
+pub struct SerdeClass {
+      #[doc(hidden)]
+      pub class: fn() -> &'static ClassInfo,
+      #[doc(hidden)]
+      pub name: &'static str,
+      #[doc(hidden)]
+      pub serialize: fn(object: *const u8) -> *const dyn erased_serde::Serialize,
+      #[doc(hidden)]
+      pub deserialize: DeserializeFn,
+      /// Set by the `version` option
+      #[doc(hidden)]
+      pub migrate: Option<Migrate>,
+}
+
+impl SerdeClass {
+      pub fn class(&self) -> &'static ClassInfo {
+            (self.class)()
+      }
+
+      /// Name written in the "class" field
+      pub fn name(&self) -> &'static str {
+            self.name
+      }
+}
+
+/// Serialization information of 'class', None if it did not opt in
+pub fn of(class: &ClassInfo) -> Option<&'static SerdeClass> {
+      SERIALIZABLE.iter().find(|serde| serde.class() == class)
+}
+
+/// Class written as 'name' in serialized data
+/// Fails with the paths of the classes if several share the name, as none of them could be read back
+pub fn by_name(name: &str) -> Result<&'static SerdeClass, LookupError> {
+      let named: Vec<_> = SERIALIZABLE.iter().filter(|serde| serde.name == name).collect();
+      match named.as_slice() {
+            [] => Err(LookupError::UnknownClass),
+            [class] => Ok(class),
+            _ => Err(LookupError::Ambiguous(named.iter().map(|serde| serde.class().path()).collect())),
+      }
+}
+
+/// The class written as 'name', or a message for serde errors
+fn lookup(name: &str) -> Result<&'static SerdeClass, String> {
+      by_name(name).map_err(|err| match err {
+            LookupError::UnknownClass => format!("unknown class {}", name),
+            LookupError::Ambiguous(paths) => format!("serde name {} is shared by the classes {}", name, paths.join(", ")),
+      })
+}
+
+/// Name of 'class' in serialized data
+fn nameOf(class: &ClassInfo) -> &'static str {
+      of(class).map_or(class.path(), |serde| serde.name)
+}
+
+/// Version of every versioned level of a class, written before its data
+pub(crate) struct Versions(Vec<(&'static str, u32)>);
+
+impl Versions {
+      pub(crate) fn of(class: &'static ClassInfo) -> Self {
+            Versions(class.ancestors().filter_map(|class| Some((nameOf(class), class.version?))).collect())
+      }
+
+      pub(crate) fn is_empty(&self) -> bool {
+            self.0.is_empty()
+      }
+}
+
+impl Serialize for Versions {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().copied())
+      }
+}
+
+/// Versions read from serialized data, levels missing from it are at version 1
+pub(crate) type SavedVersions = BTreeMap<String, u32>;
+
+fn versioned(class: &'static ClassInfo) -> bool {
+      class.ancestors().any(|class| class.version.is_some())
+}
+
+fn saved(class: &ClassInfo, versions: &SavedVersions) -> u32 {
+      versions.get(nameOf(class)).copied().unwrap_or(1)
+}
+
+fn needsMigration(class: &'static ClassInfo, versions: &SavedVersions) -> bool {
+      class.ancestors().any(|class| class.version.is_some_and(|version| version != saved(class, versions)))
+}
+
+/// Migrate the data of every level of 'class', parents first so each migration sees its parent up to date
+fn migrate(class: &'static ClassInfo, mut value: Value, versions: &SavedVersions) -> Result<Value, value::Error> {
+      if let Some(field) = class.fields().iter().find(|field| field.parent.is_some()) {
+            if let (Some(parent), Some(data)) = (field.parent_class(), value.get_mut(field.name())) {
+                  *data = migrate(parent, core::mem::replace(data, Value::Unit), versions)?;
+            }
+      }
+      let current = match class.version {
+            Some(version) => version,
+            None => return Ok(value),
+      };
+      let saved = saved(class, versions);
+      if saved == current {
+            return Ok(value);
+      }
+      if saved > current {
+            return Err(ser::Error::custom(format_args!("data of class {} has version {}, newer than {}", class.path(), saved, current)));
+      }
+      of(class)
+            .and_then(|serde| serde.migrate)
+            .map_or(Ok(None), |migrate| migrate(saved, value))?
+            .ok_or_else(|| ser::Error::custom(format_args!("no migration of class {} from version {}", class.path(), saved)))
+}
+
+impl<T: Class, C: Deref> Serialize for Object<T, C> {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let class = of(self.class).ok_or_else(|| {
+                  ser::Error::custom(format_args!("class {} is not serializable", self.class.path()))
+            })?;
+            lookup(class.name).map_err(ser::Error::custom)?;
+            let data = unsafe { &*(class.serialize)(self.base()) };
+            let versions = Versions::of(self.class);
+            let mut object = serializer.serialize_struct("Object", if versions.is_empty() { 2 } else { 3 })?;
+            object.serialize_field("class", class.name)?;
+            if versions.is_empty() {
+                  object.skip_field("versions")?;
+            } else {
+                  object.serialize_field("versions", &versions)?;
+            }
+            object.serialize_field("data", data)?;
+            object.end()
+      }
+}
+
+/// Deserializes the "data" of an object of a known class
+///
+/// The data is read as a [`Value`] when it may need migrating,
+/// which is known once the saved versions are
+pub(crate) struct Data<'a> {
+      pub(crate) class: &'static SerdeClass,
+      pub(crate) versions: Option<&'a SavedVersions>,
+}
+
+/// Data read by [`Data`]
+pub(crate) enum Loaded {
+      Object(Object<DynamicObjectBase>),
+      Pending(Value),
+}
+
+impl Loaded {
+      /// The object, after migrating its data if needed and checking its invariants
+      pub(crate) fn finish<E: de::Error>(self, class: &'static SerdeClass, versions: &SavedVersions) -> Result<Object<DynamicObjectBase>, E> {
+            let object = match self {
+                  Loaded::Object(object) => object,
+                  Loaded::Pending(value) => {
+                        let value = migrate(class.class(), value, versions).map_err(E::custom)?;
+                        let mut deserializer = <dyn erased_serde::Deserializer>::erase(value);
+                        (class.deserialize)(&mut deserializer).map_err(E::custom)?
+                  },
+            };
+            object.check_invariants().map_err(E::custom)?;
+            Ok(object)
+      }
+}
+
+impl<'de> de::DeserializeSeed<'de> for Data<'_> {
+      type Value = Loaded;
+
+      fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+            let class = self.class.class();
+            let direct = match self.versions {
+                  Some(versions) => !needsMigration(class, versions),
+                  None => !versioned(class),
+            };
+            if !direct {
+                  return Value::deserialize(deserializer).map(Loaded::Pending);
+            }
+            let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+            (self.class.deserialize)(&mut deserializer).map(Loaded::Object).map_err(de::Error::custom)
+      }
+}
+
+pub(crate) fn class<E: de::Error>(name: &str) -> Result<&'static SerdeClass, E> {
+      lookup(name).map_err(E::custom)
+}
+
+/// Cast a deserialized object to the requested base class
+fn upcast<T: Class, E: de::Error>(object: Object<DynamicObjectBase>) -> Result<Object<T>, E> {
+      if !object.class().is_subclass_of(T::info()) {
+            return Err(E::custom(format_args!("class {} does not inherit from {}", object.class().path(), T::info().path())));
+      }
+      Ok(object.cast())
+}
+
+struct ObjectVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Class> de::Visitor<'de> for ObjectVisitor<T> {
+      type Value = Object<T>;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an object with a class and data")
+      }
+
+      fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let name: String = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            let class = class(&name)?;
+            // Versions are only written for versioned classes
+            let versions: SavedVersions = if versioned(class.class()) {
+                  seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?
+            } else {
+                  SavedVersions::new()
+            };
+            let data = Data {
+                  class,
+                  versions: Some(&versions),
+            };
+            let loaded = seq.next_element_seed(data)?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+            upcast(loaded.finish(class, &versions)?)
+      }
+
+      fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut class = None;
+            let mut versions = None;
+            let mut loaded = None;
+            while let Some(key) = map.next_key::<String>()? {
+                  match key.as_str() {
+                        "class" => {
+                              let name: String = map.next_value()?;
+                              class = Some(self::class(&name)?);
+                        },
+                        "versions" => versions = Some(map.next_value::<SavedVersions>()?),
+                        "data" => match class {
+                              Some(class) => loaded = Some(map.next_value_seed(Data { class, versions: versions.as_ref() })?),
+                              None => return Err(de::Error::custom("\"class\" must come before \"data\"")),
+                        },
+                        _ => {
+                              map.next_value::<de::IgnoredAny>()?;
+                        },
+                  }
+            }
+            let (class, loaded) = class.zip(loaded).ok_or_else(|| de::Error::missing_field("data"))?;
+            upcast(loaded.finish(class, &versions.unwrap_or_default())?)
+      }
+}
+
+impl<'de, T: Class> Deserialize<'de> for Object<T> {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_struct("Object", &["class", "versions", "data"], ObjectVisitor(PhantomData))
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use serde::{Serialize, Deserialize};
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, value::{from_value, Value}, Class, DynamicObjectBase, Object};
+
+      #[subclass(DynamicObjectBase, serde)]
+      #[derive(Serialize, Deserialize)]
+      struct Node {
+            name: String
+      }
+
+      #[subclass(Node, node, serde_name = "group")]
+      #[derive(Serialize, Deserialize)]
+      struct Group {
+            node: Node,
+            children: Vec<Object<Node>>
+      }
+
+      #[subclass(Node, node)]
+      #[derive(Serialize)]
+      struct Private {
+            node: Node
+      }
+
+      // Both written as "twin"
+      mod first {
+            use super::*;
+
+            #[subclass(DynamicObjectBase, serde_name = "twin")]
+            #[derive(Serialize, Deserialize)]
+            pub struct Shared;
+      }
+
+      mod second {
+            use super::*;
+
+            #[subclass(DynamicObjectBase, serde_name = "twin")]
+            #[derive(Serialize, Deserialize)]
+            pub struct Shared;
+      }
+
+      #[subclass(DynamicObjectBase, serde_name = "item", version = 2)]
+      #[derive(Serialize, Deserialize)]
+      struct Item {
+            name: String
+      }
+
+      impl Item {
+            // 'label' was renamed
+            fn migrate_from_v1(mut old: Value) -> Self {
+                  let label = old.remove("label").unwrap();
+                  old.insert("name", label);
+                  from_value(old).unwrap()
+            }
+      }
+
+      #[subclass(Item, item, serde_name = "weapon", version = 3)]
+      #[derive(Serialize, Deserialize)]
+      struct Weapon {
+            item: Item,
+            damage: f32
+      }
+
+      impl Weapon {
+            // 'damage' was an integer
+            fn migrate_from_v2(mut old: Value) -> Self {
+                  let damage = match old.remove("damage") {
+                        Some(Value::U64(damage)) => damage as f32,
+                        _ => 0.0,
+                  };
+                  Weapon {
+                        item: from_value(old.remove("item").unwrap()).unwrap(),
+                        damage
+                  }
+            }
+      }
+
+      fn node(name: &str) -> Object<Node> {
+            Object::new(Box::new(Node { name: name.into() }))
+      }
+
+      #[test]
+      fn round_trip() {
+            let group = Object::<Group>::new(Box::new(Group {
+                  node: Node { name: "root".into() },
+                  children: vec![node("leaf")]
+            })).cast::<Node>();
+            let json = serde_json::to_value(&group).unwrap();
+            assert!(json["class"] == "group");
+            assert!(json["data"]["children"][0]["class"] == Node::info().path());
+
+            let object: Object<Node> = serde_json::from_value(json).unwrap();
+            assert!(object.name == "root");
+            let group = object.cast::<Group>();
+            assert!(group.children[0].name == "leaf");
+            assert!(group.children[0].class() == Node::info());
+      }
+
+      #[test]
+      fn errors() {
+            let private = Object::<Private>::new(Box::new(Private { node: Node { name: String::new() } }));
+            assert!(serde_json::to_string(&private).is_err());
+            assert!(serde_json::from_str::<Object<Node>>(r#"{"class":"missing","data":{}}"#).is_err());
+            assert!(serde_json::from_str::<Object<Node>>(r#"{"data":{"name":"a"},"class":"group"}"#).is_err());
+            assert!(serde_json::from_str::<Object<Group>>(&format!(r#"{{"class":"{}","data":{{"name":"a"}}}}"#, Node::info().path())).is_err());
+      }
+
+      #[test]
+      fn duplicate_names() {
+            let error = serde_json::to_string(&Object::<first::Shared>::new(Box::new(first::Shared))).unwrap_err().to_string();
+            assert!(error.contains(first::Shared::info().path()) && error.contains(second::Shared::info().path()));
+            let error = serde_json::from_str::<Object<DynamicObjectBase>>(r#"{"class":"twin","data":null}"#).err().unwrap().to_string();
+            assert!(error.starts_with("serde name twin is shared by the classes"));
+      }
+
+      #[test]
+      fn versions() {
+            let weapon = Object::<Weapon>::new(Box::new(Weapon { item: Item { name: "axe".into() }, damage: 1.5 }));
+            let json = serde_json::to_value(&weapon).unwrap();
+            assert!(json["versions"] == serde_json::json!({ "item": 2, "weapon": 3 }));
+            let weapon: Object<Item> = serde_json::from_value(json).unwrap();
+            assert!(weapon.name == "axe");
+
+            // Each level is migrated from its own version, the parent first
+            let old = r#"{"class":"weapon","versions":{"item":1,"weapon":2},"data":{"item":{"label":"sword"},"damage":7}}"#;
+            let weapon: Object<Item> = serde_json::from_str(old).unwrap();
+            assert!(weapon.name == "sword");
+            assert!(weapon.cast::<Weapon>().damage == 7.0);
+            // Versions read after the data, missing levels are at version 1
+            let unordered = serde_json::json!({ "class": "item", "data": { "label": "shield" } });
+            let item: Object<Item> = serde_json::from_value(unordered).unwrap();
+            assert!(item.name == "shield");
+
+            let missing = r#"{"class":"weapon","versions":{"item":2,"weapon":1},"data":{"item":{"name":"bow"},"damage":2}}"#;
+            assert!(serde_json::from_str::<Object<Item>>(missing).is_err());
+            let newer = r#"{"class":"item","versions":{"item":3},"data":{"name":"bow"}}"#;
+            assert!(serde_json::from_str::<Object<Item>>(newer).is_err());
+      }
+}
@@ -0,0 +1,156 @@
+//! Snapshots and transactions
+//!
+//! A [`Snapshot`] copies the class the object was created as, not just the part it is cast to,
+//! so the class needs the `clone` option of #[subclass].
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[subclass(DynamicObjectBase, clone)]
+//! #[derive(Clone)]
+//! struct Class {
+//!       value: u32
+//! }
+//!
+//! #[subclass(Class, parent, clone)]
+//! #[derive(Clone)]
+//! struct Derived {
+//!       field: u32,
+//!       parent: Class,
+//! }
+//!
+//! let mut object = Object::<Derived>::new(Box::new(Derived { field: 1, parent: Class { value: 2 } })).cast::<Class>();
+//! let result: Result<(), &str> = object.transaction(|object| {
+//!       object.value = 3;
+//!       object.cast_mut::<Derived>().field = 4;
+//!       Err("invalid")
+//! });
+//! assert!(result.is_err());
+//! assert!(object.value == 2 && object.cast_ref::<Derived>().field == 1);
+//! ```
+use core::{marker::PhantomData, ops::{Deref, DerefMut}};
+use alloc::boxed::Box;
+
+use crate::{Class, ClassInfo, Dyn, Object};
+
+/// A copy of the state of an object, taken by [`Object::snapshot`]
+pub struct Snapshot<T: Class> {
+      class: &'static ClassInfo,
+      value: Box<dyn Dyn>,
+      _marker: PhantomData<T>,
+}
+
+impl<T: Class> Snapshot<T> {
+      /// The class the object was created as
+      pub fn class(&self) -> &'static ClassInfo {
+            self.class
+      }
+}
+
+impl<T: Class, C: Deref> Object<T, C> {
+      /// Copy the state of the object
+      /// panic if the class it was created as does not have the `clone` option
+      pub fn snapshot(&self) -> Snapshot<T> {
+            match self.try_snapshot() {
+                  Some(snapshot) => snapshot,
+                  None => panic!("class {} does not have the clone option", self.class.path())
+            }
+      }
+
+      /// Try to copy the state of the object, None if its class does not have the `clone` option
+      pub fn try_snapshot(&self) -> Option<Snapshot<T>> {
+            let clone = self.class.clone?;
+            Some(Snapshot {
+                  class: self.class,
+                  value: unsafe { clone(self.base()) },
+                  _marker: PhantomData,
+            })
+      }
+}
+
+impl<T: Class, C: DerefMut> Object<T, C> {
+      /// Put back the state copied by `snapshot`, in place
+      /// panic if the snapshot was taken from an object created as another class
+      pub fn restore(&mut self, snapshot: Snapshot<T>) {
+            assert!(snapshot.class == self.class, "cannot restore a {} snapshot into a {}", snapshot.class.path(), self.class.path());
+            let mut value = snapshot.value;
+            let size = core::mem::size_of_val(&*value);
+            // Both hold a value of the same class: the current state is dropped with the box
+            unsafe {
+                  core::ptr::swap_nonoverlapping(self.baseMut(), &mut *value as *mut dyn Dyn as *mut u8, size);
+            }
+      }
+
+      /// Run 'edit' on the object, restoring its state if it returns an error
+      /// panic if the class it was created as does not have the `clone` option
+      pub fn transaction<R, E>(&mut self, edit: impl FnOnce(&mut Self) -> Result<R, E>) -> Result<R, E> {
+            let snapshot = self.snapshot();
+            let result = edit(self);
+            if result.is_err() {
+                  self.restore(snapshot);
+            }
+            result
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use dynamic_object_derive::subclass;
+      use crate::{self as dynamic_object, DynamicObjectBase, Object};
+
+      #[subclass(DynamicObjectBase, clone)]
+      #[derive(Clone)]
+      struct Document {
+            title: String
+      }
+
+      #[subclass(Document, parent, clone)]
+      #[derive(Clone)]
+      struct Drawing {
+            parent: Document,
+            shapes: Vec<u32>,
+      }
+
+      fn drawing() -> Object<Document> {
+            Object::<Drawing>::new(Box::new(Drawing { parent: Document { title: "a".into() }, shapes: vec![1] })).cast()
+      }
+
+      #[test]
+      fn snapshot_and_restore() {
+            let mut object = drawing();
+            let snapshot = object.snapshot();
+            assert!(snapshot.class().name() == "Drawing");
+            object.title.push('b');
+            object.cast_mut::<Drawing>().shapes.push(2);
+            object.restore(snapshot);
+            assert!(object.title == "a");
+            assert!(object.cast_ref::<Drawing>().shapes == [1]);
+
+            let document = Object::<Document>::new(Box::new(Document { title: "c".into() }));
+            assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+                  let mut object = drawing();
+                  object.restore(document.snapshot());
+            })).is_err());
+      }
+
+      #[test]
+      fn transaction() {
+            let mut object = drawing();
+            let result: Result<usize, ()> = object.transaction(|object| {
+                  object.cast_mut::<Drawing>().shapes.push(2);
+                  Ok(object.cast_ref::<Drawing>().shapes.len())
+            });
+            assert!(result == Ok(2));
+
+            let result: Result<(), &str> = object.transaction(|object| {
+                  object.title.clear();
+                  object.cast_mut::<Drawing>().shapes.clear();
+                  Err("empty title")
+            });
+            assert!(result == Err("empty title"));
+            assert!(object.title == "a" && object.cast_ref::<Drawing>().shapes == [1, 2]);
+      }
+}
@@ -54,7 +54,7 @@ impl<A: Class, B: Class> IsSameClass<A, B> {
 
 pub const fn isSubclassOf<Child: Class, Parent: Class>() -> bool {
       if IsSameClass::<Child, Parent>::VALUE {
-            return true
+            true
       }
       else if IsSameClass::<Child, DynamicObjectBase>::VALUE {
             false
@@ -64,12 +64,18 @@ pub const fn isSubclassOf<Child: Class, Parent: Class>() -> bool {
       }
 }
 
+/// Offset of 'Parent' inside 'Child', 0 if they are the same class
+/// panic if 'Child' does not inherit from 'Parent'
 pub fn offsetOf<Parent: Class, Child: Class>() -> isize {
-      let mut offset = Child::offset();
-      if !IsSameClass::<Parent, Child>::VALUE {
-            offset += offsetOf::<Parent, Child::Parent>();
+      if IsSameClass::<Parent, Child>::VALUE {
+            0
+      }
+      else if IsSameClass::<Child, DynamicObjectBase>::VALUE {
+            panic!("{} is not a parent of the class", Parent::NAME)
+      }
+      else {
+            Child::offset() + offsetOf::<Parent, Child::Parent>()
       }
-      offset
 }
 
 #[cfg(test)]
@@ -88,9 +94,9 @@ mod test {
 
       #[test]
       fn isSameClass() {
-            assert!(IsSameClass::<DynamicObjectBase, DynamicObjectBase>::VALUE);
-            assert!(!IsSameClass::<MyClass, DynamicObjectBase>::VALUE);
-            assert!(IsSameClass::<MyClass, MyClass>::VALUE);
+            const { assert!(IsSameClass::<DynamicObjectBase, DynamicObjectBase>::VALUE) };
+            const { assert!(!IsSameClass::<MyClass, DynamicObjectBase>::VALUE) };
+            const { assert!(IsSameClass::<MyClass, MyClass>::VALUE) };
       }
 
       #[test]
@@ -105,9 +111,7 @@ mod test {
                   value: u32,
                   value2: u32
             }
-            let a = 0 as *mut A;
-            let ptr: *mut u32 = unsafe { &mut ((*a).value2) };
-            let ptr = ptr as usize;
+            let ptr = core::mem::offset_of!(A, value2);
             assert!(ptr == 4);
       }
 }
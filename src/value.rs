@@ -0,0 +1,235 @@
+//! Dynamically typed values
+use core::fmt;
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use crate::format::Probe;
+
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "serde")]
+pub use self::serde::{from_value, to_value, Error};
+
+/// A value whose type is only known at runtime
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+      Unit,
+      Bool(bool),
+      I8(i8),
+      I16(i16),
+      I32(i32),
+      I64(i64),
+      U8(u8),
+      U16(u16),
+      U32(u32),
+      U64(u64),
+      F32(f32),
+      F64(f64),
+      Char(char),
+      String(String),
+      List(Vec<Value>),
+      Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+      /// Name of the variant
+      pub fn type_name(&self) -> &'static str {
+            match self {
+                  Value::Unit => "()",
+                  Value::Bool(_) => "bool",
+                  Value::I8(_) => "i8",
+                  Value::I16(_) => "i16",
+                  Value::I32(_) => "i32",
+                  Value::I64(_) => "i64",
+                  Value::U8(_) => "u8",
+                  Value::U16(_) => "u16",
+                  Value::U32(_) => "u32",
+                  Value::U64(_) => "u64",
+                  Value::F32(_) => "f32",
+                  Value::F64(_) => "f64",
+                  Value::Char(_) => "char",
+                  Value::String(_) => "String",
+                  Value::List(_) => "List",
+                  Value::Map(_) => "Map",
+            }
+      }
+
+      /// Entry 'key' of a map
+      pub fn get(&self, key: &str) -> Option<&Value> {
+            match self {
+                  Value::Map(map) => map.get(key),
+                  _ => None,
+            }
+      }
+
+      /// Mutable entry 'key' of a map
+      pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+            match self {
+                  Value::Map(map) => map.get_mut(key),
+                  _ => None,
+            }
+      }
+
+      /// Set entry 'key' of a map, returning the previous value
+      /// None if 'self' is not a map
+      pub fn insert(&mut self, key: &str, value: impl Into<Value>) -> Option<Value> {
+            match self {
+                  Value::Map(map) => map.insert(key.into(), value.into()),
+                  _ => None,
+            }
+      }
+
+      /// Remove entry 'key' of a map
+      pub fn remove(&mut self, key: &str) -> Option<Value> {
+            match self {
+                  Value::Map(map) => map.remove(key),
+                  _ => None,
+            }
+      }
+}
+
+impl fmt::Display for Value {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                  Value::Unit => write!(f, "()"),
+                  Value::Bool(value) => write!(f, "{}", value),
+                  Value::I8(value) => write!(f, "{}", value),
+                  Value::I16(value) => write!(f, "{}", value),
+                  Value::I32(value) => write!(f, "{}", value),
+                  Value::I64(value) => write!(f, "{}", value),
+                  Value::U8(value) => write!(f, "{}", value),
+                  Value::U16(value) => write!(f, "{}", value),
+                  Value::U32(value) => write!(f, "{}", value),
+                  Value::U64(value) => write!(f, "{}", value),
+                  Value::F32(value) => write!(f, "{}", value),
+                  Value::F64(value) => write!(f, "{}", value),
+                  Value::Char(value) => write!(f, "{}", value),
+                  Value::String(value) => write!(f, "{}", value),
+                  Value::List(values) => {
+                        write!(f, "[")?;
+                        for (i, value) in values.iter().enumerate() {
+                              if i > 0 {
+                                    write!(f, ", ")?;
+                              }
+                              write!(f, "{}", value)?;
+                        }
+                        write!(f, "]")
+                  },
+                  Value::Map(map) => {
+                        write!(f, "{{")?;
+                        for (i, (key, value)) in map.iter().enumerate() {
+                              if i > 0 {
+                                    write!(f, ", ")?;
+                              }
+                              write!(f, "{}: {}", key, value)?;
+                        }
+                        write!(f, "}}")
+                  },
+            }
+      }
+}
+
+/// Conversion out of a [`Value`]
+pub trait FromValue: Sized {
+      /// None if 'value' does not hold a 'Self'
+      fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl From<()> for Value {
+      fn from(_: ()) -> Self {
+            Value::Unit
+      }
+}
+
+impl FromValue for () {
+      fn from_value(value: &Value) -> Option<Self> {
+            match value {
+                  Value::Unit => Some(()),
+                  _ => None,
+            }
+      }
+}
+
+macro_rules! value {
+      ($($variant:ident $type:ty),*) => {
+            $(
+                  impl From<$type> for Value {
+                        fn from(value: $type) -> Self {
+                              Value::$variant(value)
+                        }
+                  }
+
+                  impl FromValue for $type {
+                        fn from_value(value: &Value) -> Option<Self> {
+                              match value {
+                                    Value::$variant(value) => Some(value.clone()),
+                                    _ => None,
+                              }
+                        }
+                  }
+            )*
+      };
+}
+
+value!(
+      Bool bool,
+      I8 i8, I16 i16, I32 i32, I64 i64,
+      U8 u8, U16 u16, U32 u32, U64 u64,
+      F32 f32, F64 f64,
+      Char char,
+      String String,
+      List Vec<Value>,
+      Map BTreeMap<String, Value>
+);
+
+/// Converts fields between their type and Value in generated code, see [`Probe`]
+#[doc(hidden)]
+pub trait ProbeValue {
+      unsafe fn to_value(&self, field: *const u8) -> Option<Value>;
+      unsafe fn set_value(&self, field: *mut u8, value: &Value) -> bool;
+      fn accepts_value(&self, value: &Value) -> bool;
+}
+
+impl<T: Clone + Into<Value> + FromValue> ProbeValue for Probe<T> {
+      unsafe fn to_value(&self, field: *const u8) -> Option<Value> {
+            Some((*(field as *const T)).clone().into())
+      }
+
+      unsafe fn set_value(&self, field: *mut u8, value: &Value) -> bool {
+            match T::from_value(value) {
+                  Some(value) => {
+                        *(field as *mut T) = value;
+                        true
+                  },
+                  None => false,
+            }
+      }
+
+      fn accepts_value(&self, value: &Value) -> bool {
+            T::from_value(value).is_some()
+      }
+}
+
+#[doc(hidden)]
+pub trait NoValue {
+      unsafe fn to_value(&self, _: *const u8) -> Option<Value> {
+            None
+      }
+
+      unsafe fn set_value(&self, _: *mut u8, _: &Value) -> bool {
+            false
+      }
+
+      fn accepts_value(&self, _: &Value) -> bool {
+            false
+      }
+}
+
+impl<T> NoValue for &Probe<T> {
+
+}
+
+impl From<&str> for Value {
+      fn from(value: &str) -> Self {
+            Value::String(value.into())
+      }
+}
@@ -0,0 +1,696 @@
+//! Conversion between [`Value`] and serde types
+//!
+//! Structs and maps become [`Value::Map`], sequences and tuples [`Value::List`], `None` and unit
+//! become [`Value::Unit`]. Enum variants are written like serde_json does:
+//! the name for unit variants, `{ name: content }` for the others.
+use core::fmt::{self, Display};
+use alloc::{borrow::ToOwned, collections::{btree_map, BTreeMap}, string::{String, ToString}, vec::Vec};
+use serde::{de::{self, IntoDeserializer}, forward_to_deserialize_any, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Value;
+
+/// Error of [`to_value`] and [`from_value`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error(String);
+
+impl Display for Error {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.0)
+      }
+}
+
+impl core::error::Error for Error {
+
+}
+
+impl ser::Error for Error {
+      fn custom<T: Display>(message: T) -> Self {
+            Error(message.to_string())
+      }
+}
+
+impl de::Error for Error {
+      fn custom<T: Display>(message: T) -> Self {
+            Error(message.to_string())
+      }
+}
+
+/// Convert 'value' to a [`Value`]
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+      value.serialize(ValueSerializer)
+}
+
+/// Read a 'T' from a [`Value`]
+pub fn from_value<T: de::DeserializeOwned>(value: Value) -> Result<T, Error> {
+      T::deserialize(value)
+}
+
+impl Serialize for Value {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                  Value::Unit => serializer.serialize_unit(),
+                  Value::Bool(value) => serializer.serialize_bool(*value),
+                  Value::I8(value) => serializer.serialize_i8(*value),
+                  Value::I16(value) => serializer.serialize_i16(*value),
+                  Value::I32(value) => serializer.serialize_i32(*value),
+                  Value::I64(value) => serializer.serialize_i64(*value),
+                  Value::U8(value) => serializer.serialize_u8(*value),
+                  Value::U16(value) => serializer.serialize_u16(*value),
+                  Value::U32(value) => serializer.serialize_u32(*value),
+                  Value::U64(value) => serializer.serialize_u64(*value),
+                  Value::F32(value) => serializer.serialize_f32(*value),
+                  Value::F64(value) => serializer.serialize_f64(*value),
+                  Value::Char(value) => serializer.serialize_char(*value),
+                  Value::String(value) => serializer.serialize_str(value),
+                  Value::List(values) => serializer.collect_seq(values),
+                  Value::Map(map) => serializer.collect_map(map),
+            }
+      }
+}
+
+struct ValueVisitor;
+
+impl<'de> de::Visitor<'de> for ValueVisitor {
+      type Value = Value;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "any value")
+      }
+
+      fn visit_bool<E>(self, value: bool) -> Result<Value, E> {
+            Ok(Value::Bool(value))
+      }
+
+      fn visit_i8<E>(self, value: i8) -> Result<Value, E> {
+            Ok(Value::I8(value))
+      }
+
+      fn visit_i16<E>(self, value: i16) -> Result<Value, E> {
+            Ok(Value::I16(value))
+      }
+
+      fn visit_i32<E>(self, value: i32) -> Result<Value, E> {
+            Ok(Value::I32(value))
+      }
+
+      fn visit_i64<E>(self, value: i64) -> Result<Value, E> {
+            Ok(Value::I64(value))
+      }
+
+      fn visit_u8<E>(self, value: u8) -> Result<Value, E> {
+            Ok(Value::U8(value))
+      }
+
+      fn visit_u16<E>(self, value: u16) -> Result<Value, E> {
+            Ok(Value::U16(value))
+      }
+
+      fn visit_u32<E>(self, value: u32) -> Result<Value, E> {
+            Ok(Value::U32(value))
+      }
+
+      fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
+            Ok(Value::U64(value))
+      }
+
+      fn visit_f32<E>(self, value: f32) -> Result<Value, E> {
+            Ok(Value::F32(value))
+      }
+
+      fn visit_f64<E>(self, value: f64) -> Result<Value, E> {
+            Ok(Value::F64(value))
+      }
+
+      fn visit_char<E>(self, value: char) -> Result<Value, E> {
+            Ok(Value::Char(value))
+      }
+
+      fn visit_str<E>(self, value: &str) -> Result<Value, E> {
+            Ok(Value::String(value.to_owned()))
+      }
+
+      fn visit_string<E>(self, value: String) -> Result<Value, E> {
+            Ok(Value::String(value))
+      }
+
+      fn visit_bytes<E>(self, value: &[u8]) -> Result<Value, E> {
+            Ok(Value::List(value.iter().copied().map(Value::U8).collect()))
+      }
+
+      fn visit_unit<E>(self) -> Result<Value, E> {
+            Ok(Value::Unit)
+      }
+
+      fn visit_none<E>(self) -> Result<Value, E> {
+            Ok(Value::Unit)
+      }
+
+      fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+            Value::deserialize(deserializer)
+      }
+
+      fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+            Value::deserialize(deserializer)
+      }
+
+      fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+            let mut values = Vec::new();
+            while let Some(value) = seq.next_element()? {
+                  values.push(value);
+            }
+            Ok(Value::List(values))
+      }
+
+      fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+            let mut values = BTreeMap::new();
+            while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+                  values.insert(key.to_string(), value);
+            }
+            Ok(Value::Map(values))
+      }
+}
+
+impl<'de> Deserialize<'de> for Value {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ValueVisitor)
+      }
+}
+
+/// Builds a [`Value`] from any serializable type
+struct ValueSerializer;
+
+fn variant(name: &'static str, value: Value) -> Value {
+      Value::Map(BTreeMap::from([(name.to_owned(), value)]))
+}
+
+impl Serializer for ValueSerializer {
+      type Ok = Value;
+      type Error = Error;
+      type SerializeSeq = SerializeList;
+      type SerializeTuple = SerializeList;
+      type SerializeTupleStruct = SerializeList;
+      type SerializeTupleVariant = SerializeList;
+      type SerializeMap = SerializeMap;
+      type SerializeStruct = SerializeMap;
+      type SerializeStructVariant = SerializeMap;
+
+      fn serialize_bool(self, value: bool) -> Result<Value, Error> {
+            Ok(Value::Bool(value))
+      }
+
+      fn serialize_i8(self, value: i8) -> Result<Value, Error> {
+            Ok(Value::I8(value))
+      }
+
+      fn serialize_i16(self, value: i16) -> Result<Value, Error> {
+            Ok(Value::I16(value))
+      }
+
+      fn serialize_i32(self, value: i32) -> Result<Value, Error> {
+            Ok(Value::I32(value))
+      }
+
+      fn serialize_i64(self, value: i64) -> Result<Value, Error> {
+            Ok(Value::I64(value))
+      }
+
+      fn serialize_u8(self, value: u8) -> Result<Value, Error> {
+            Ok(Value::U8(value))
+      }
+
+      fn serialize_u16(self, value: u16) -> Result<Value, Error> {
+            Ok(Value::U16(value))
+      }
+
+      fn serialize_u32(self, value: u32) -> Result<Value, Error> {
+            Ok(Value::U32(value))
+      }
+
+      fn serialize_u64(self, value: u64) -> Result<Value, Error> {
+            Ok(Value::U64(value))
+      }
+
+      fn serialize_f32(self, value: f32) -> Result<Value, Error> {
+            Ok(Value::F32(value))
+      }
+
+      fn serialize_f64(self, value: f64) -> Result<Value, Error> {
+            Ok(Value::F64(value))
+      }
+
+      fn serialize_char(self, value: char) -> Result<Value, Error> {
+            Ok(Value::Char(value))
+      }
+
+      fn serialize_str(self, value: &str) -> Result<Value, Error> {
+            Ok(Value::String(value.to_owned()))
+      }
+
+      fn serialize_bytes(self, value: &[u8]) -> Result<Value, Error> {
+            Ok(Value::List(value.iter().copied().map(Value::U8).collect()))
+      }
+
+      fn serialize_none(self) -> Result<Value, Error> {
+            Ok(Value::Unit)
+      }
+
+      fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+            value.serialize(self)
+      }
+
+      fn serialize_unit(self) -> Result<Value, Error> {
+            Ok(Value::Unit)
+      }
+
+      fn serialize_unit_struct(self, _: &'static str) -> Result<Value, Error> {
+            Ok(Value::Unit)
+      }
+
+      fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<Value, Error> {
+            Ok(Value::String(variant.to_owned()))
+      }
+
+      fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T) -> Result<Value, Error> {
+            value.serialize(self)
+      }
+
+      fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, name: &'static str, value: &T) -> Result<Value, Error> {
+            Ok(variant(name, to_value(value)?))
+      }
+
+      fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+            Ok(SerializeList {
+                  variant: None,
+                  values: Vec::with_capacity(len.unwrap_or(0)),
+            })
+      }
+
+      fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+            self.serialize_seq(Some(len))
+      }
+
+      fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeList, Error> {
+            self.serialize_seq(Some(len))
+      }
+
+      fn serialize_tuple_variant(self, _: &'static str, _: u32, name: &'static str, len: usize) -> Result<SerializeList, Error> {
+            Ok(SerializeList {
+                  variant: Some(name),
+                  values: Vec::with_capacity(len),
+            })
+      }
+
+      fn serialize_map(self, _: Option<usize>) -> Result<SerializeMap, Error> {
+            Ok(SerializeMap {
+                  variant: None,
+                  values: BTreeMap::new(),
+                  key: None,
+            })
+      }
+
+      fn serialize_struct(self, _: &'static str, len: usize) -> Result<SerializeMap, Error> {
+            self.serialize_map(Some(len))
+      }
+
+      fn serialize_struct_variant(self, _: &'static str, _: u32, name: &'static str, _: usize) -> Result<SerializeMap, Error> {
+            Ok(SerializeMap {
+                  variant: Some(name),
+                  values: BTreeMap::new(),
+                  key: None,
+            })
+      }
+}
+
+struct SerializeList {
+      variant: Option<&'static str>,
+      values: Vec<Value>,
+}
+
+impl SerializeList {
+      fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.values.push(to_value(value)?);
+            Ok(())
+      }
+
+      fn finish(self) -> Result<Value, Error> {
+            let values = Value::List(self.values);
+            Ok(match self.variant {
+                  Some(name) => variant(name, values),
+                  None => values,
+            })
+      }
+}
+
+impl ser::SerializeSeq for SerializeList {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+impl ser::SerializeTuple for SerializeList {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+struct SerializeMap {
+      variant: Option<&'static str>,
+      values: BTreeMap<String, Value>,
+      key: Option<String>,
+}
+
+impl SerializeMap {
+      fn finish(self) -> Result<Value, Error> {
+            let values = Value::Map(self.values);
+            Ok(match self.variant {
+                  Some(name) => variant(name, values),
+                  None => values,
+            })
+      }
+}
+
+impl ser::SerializeMap for SerializeMap {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+            match to_value(key)? {
+                  Value::List(_) | Value::Map(_) => Err(Error("map keys must be strings or primitives".to_owned())),
+                  key => {
+                        self.key = Some(key.to_string());
+                        Ok(())
+                  }
+            }
+      }
+
+      fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            let key = self.key.take().ok_or_else(|| Error("value serialized before its key".to_owned()))?;
+            self.values.insert(key, to_value(value)?);
+            Ok(())
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+            self.values.insert(key.to_owned(), to_value(value)?);
+            Ok(())
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+      type Ok = Value;
+      type Error = Error;
+
+      fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+            self.values.insert(key.to_owned(), to_value(value)?);
+            Ok(())
+      }
+
+      fn end(self) -> Result<Value, Error> {
+            self.finish()
+      }
+}
+
+impl<'de> Deserializer<'de> for Value {
+      type Error = Error;
+
+      fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self {
+                  Value::Unit => visitor.visit_unit(),
+                  Value::Bool(value) => visitor.visit_bool(value),
+                  Value::I8(value) => visitor.visit_i8(value),
+                  Value::I16(value) => visitor.visit_i16(value),
+                  Value::I32(value) => visitor.visit_i32(value),
+                  Value::I64(value) => visitor.visit_i64(value),
+                  Value::U8(value) => visitor.visit_u8(value),
+                  Value::U16(value) => visitor.visit_u16(value),
+                  Value::U32(value) => visitor.visit_u32(value),
+                  Value::U64(value) => visitor.visit_u64(value),
+                  Value::F32(value) => visitor.visit_f32(value),
+                  Value::F64(value) => visitor.visit_f64(value),
+                  Value::Char(value) => visitor.visit_char(value),
+                  Value::String(value) => visitor.visit_string(value),
+                  Value::List(values) => visitor.visit_seq(de::value::SeqDeserializer::new(values.into_iter())),
+                  Value::Map(values) => visitor.visit_map(MapDeserializer::new(values)),
+            }
+      }
+
+      fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self {
+                  Value::Unit => visitor.visit_none(),
+                  value => visitor.visit_some(value),
+            }
+      }
+
+      fn deserialize_newtype_struct<V: de::Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+      }
+
+      fn deserialize_enum<V: de::Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+            match self {
+                  Value::String(name) => visitor.visit_enum(name.into_deserializer()),
+                  Value::Map(values) if values.len() == 1 => {
+                        let (name, value) = values.into_iter().next().unwrap();
+                        visitor.visit_enum(EnumDeserializer { name, value })
+                  },
+                  value => Err(de::Error::invalid_type(unexpected(&value), &"an enum variant")),
+            }
+      }
+
+      forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+      }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+      type Deserializer = Value;
+
+      fn into_deserializer(self) -> Value {
+            self
+      }
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+      match value {
+            Value::List(_) => de::Unexpected::Seq,
+            Value::Map(_) => de::Unexpected::Map,
+            _ => de::Unexpected::Other(value.type_name()),
+      }
+}
+
+struct MapDeserializer {
+      entries: btree_map::IntoIter<String, Value>,
+      value: Option<Value>,
+}
+
+impl MapDeserializer {
+      fn new(values: BTreeMap<String, Value>) -> Self {
+            Self {
+                  entries: values.into_iter(),
+                  value: None,
+            }
+      }
+}
+
+impl<'de> de::MapAccess<'de> for MapDeserializer {
+      type Error = Error;
+
+      fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+            match self.entries.next() {
+                  Some((key, value)) => {
+                        self.value = Some(value);
+                        seed.deserialize(KeyDeserializer(key)).map(Some)
+                  },
+                  None => Ok(None),
+            }
+      }
+
+      fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+            let value = self.value.take().ok_or_else(|| Error("value read before its key".to_owned()))?;
+            seed.deserialize(value)
+      }
+}
+
+/// Map keys are stored as strings, parse them back when a number is expected
+struct KeyDeserializer(String);
+
+macro_rules! parseKey {
+      ($($deserialize:ident $visit:ident $type:ty),*) => {
+            $(
+                  fn $deserialize<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                        match self.0.parse::<$type>() {
+                              Ok(value) => visitor.$visit(value),
+                              Err(_) => visitor.visit_string(self.0),
+                        }
+                  }
+            )*
+      };
+}
+
+impl<'de> Deserializer<'de> for KeyDeserializer {
+      type Error = Error;
+
+      fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_string(self.0)
+      }
+
+      parseKey!(
+            deserialize_bool visit_bool bool,
+            deserialize_i8 visit_i8 i8, deserialize_i16 visit_i16 i16,
+            deserialize_i32 visit_i32 i32, deserialize_i64 visit_i64 i64,
+            deserialize_u8 visit_u8 u8, deserialize_u16 visit_u16 u16,
+            deserialize_u32 visit_u32 u32, deserialize_u64 visit_u64 u64,
+            deserialize_f32 visit_f32 f32, deserialize_f64 visit_f64 f64,
+            deserialize_char visit_char char
+      );
+
+      fn deserialize_newtype_struct<V: de::Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+      }
+
+      fn deserialize_enum<V: de::Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_enum(self.0.into_deserializer())
+      }
+
+      forward_to_deserialize_any! {
+            i128 u128 str string bytes byte_buf option unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+      }
+}
+
+struct EnumDeserializer {
+      name: String,
+      value: Value,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+      type Error = Error;
+      type Variant = Value;
+
+      fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Value), Error> {
+            let name = seed.deserialize(self.name.into_deserializer())?;
+            Ok((name, self.value))
+      }
+}
+
+impl<'de> de::VariantAccess<'de> for Value {
+      type Error = Error;
+
+      fn unit_variant(self) -> Result<(), Error> {
+            match self {
+                  Value::Unit => Ok(()),
+                  value => Err(de::Error::invalid_type(unexpected(&value), &"a unit variant")),
+            }
+      }
+
+      fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+            seed.deserialize(self)
+      }
+
+      fn tuple_variant<V: de::Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_any(visitor)
+      }
+
+      fn struct_variant<V: de::Visitor<'de>>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_any(visitor)
+      }
+}
+
+#[cfg(test)]
+mod test {
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use std::collections::HashMap;
+      use serde::{Serialize, Deserialize};
+      use super::*;
+
+      #[derive(Serialize, Deserialize, PartialEq, Debug)]
+      enum Shape {
+            Empty,
+            Circle(f32),
+            Rect { w: u32, h: u32 },
+      }
+
+      #[derive(Serialize, Deserialize, PartialEq, Debug)]
+      struct Scene {
+            name: String,
+            shapes: Vec<Shape>,
+            layers: HashMap<u8, Option<bool>>,
+      }
+
+      #[test]
+      fn round_trip() {
+            let scene = Scene {
+                  name: "scene".into(),
+                  shapes: vec![Shape::Empty, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+                  layers: HashMap::from([(1, Some(true)), (2, None)]),
+            };
+            let value = to_value(&scene).unwrap();
+            assert!(value.get("name") == Some(&Value::from("scene")));
+            assert!(value.get("shapes").unwrap().to_string() == "[Empty, {Circle: 1.5}, {Rect: {h: 3, w: 2}}]");
+            assert!(from_value::<Scene>(value.clone()).unwrap() == scene);
+
+            let json = serde_json::to_string(&value).unwrap();
+            let parsed: Value = serde_json::from_str(&json).unwrap();
+            assert!(from_value::<Scene>(parsed).unwrap() == scene);
+      }
+
+      #[test]
+      fn errors() {
+            assert!(from_value::<u32>(Value::from("text")).is_err());
+            assert!(from_value::<Shape>(Value::List(vec![])).is_err());
+            let keys = HashMap::from([(vec![1], 2)]);
+            assert!(to_value(&keys).is_err());
+      }
+}
@@ -0,0 +1,163 @@
+//! Visitors over class hierarchies
+//!
+//! `#[visitable]` is put on an inline module holding a class hierarchy.
+//! It generates a `Visitor` trait with a `visit_<class>` method per class whose default calls the visit method of the parent,
+//! a `VisitorMut` trait with `visit_<class>_mut` methods, and implements [`Visitable`]/[`VisitableMut`] for every class.
+//!
+//! # Example:
+//! ```
+//! # use dynamic_object::*;
+//! #[visitable]
+//! mod shapes {
+//!       use dynamic_object::*;
+//!
+//!       #[subclass(DynamicObjectBase)]
+//!       pub struct Shape {
+//!             pub id: u32
+//!       }
+//!
+//!       #[subclass(Shape, shape)]
+//!       pub struct Circle {
+//!             pub shape: Shape,
+//!             pub radius: f32
+//!       }
+//! }
+//! use shapes::*;
+//!
+//! struct Ids(Vec<u32>);
+//!
+//! impl Visitor for Ids {
+//!       fn visit_shape(&mut self, shape: &Shape) {
+//!             self.0.push(shape.id);
+//!       }
+//! }
+//!
+//! let object = Object::<Circle>::new(Box::new(Circle { shape: Shape { id: 1 }, radius: 2.0 }));
+//! let mut ids = Ids(Vec::new());
+//! // visit_circle is not overridden, so it calls visit_shape
+//! object.cast::<Shape>().accept(&mut ids);
+//! assert!(ids.0 == [1]);
+//! ```
+use core::ops::{Deref, DerefMut};
+
+use crate::{Class, Object};
+
+/// Implemented by #[visitable] for every class of the hierarchy
+pub trait Visitable<V: ?Sized>: Class + Sized {
+      /// Calls the visit method of the dynamic type of 'object'
+      fn accept<C: Deref>(object: &Object<Self, C>, visitor: &mut V);
+}
+
+/// Implemented by #[visitable] for every class of the hierarchy
+pub trait VisitableMut<V: ?Sized>: Class + Sized {
+      /// Calls the mutable visit method of the dynamic type of 'object'
+      fn accept_mut<C: DerefMut>(object: &mut Object<Self, C>, visitor: &mut V);
+}
+
+impl<T: Class, C: Deref> Object<T, C> {
+      /// Dispatch to the visit method of the object's dynamic type
+      pub fn accept<V: ?Sized>(&self, visitor: &mut V)
+      where
+            T: Visitable<V>
+      {
+            T::accept(self, visitor)
+      }
+}
+
+impl<T: Class, C: DerefMut> Object<T, C> {
+      /// Dispatch to the mutable visit method of the object's dynamic type
+      pub fn accept_mut<V: ?Sized>(&mut self, visitor: &mut V)
+      where
+            T: VisitableMut<V>
+      {
+            T::accept_mut(self, visitor)
+      }
+}
+
+#[cfg(test)]
+mod test {
+      #![allow(dead_code)]
+      extern crate std;
+      use std::prelude::rust_2021::*;
+      use crate::{self as dynamic_object, Object};
+
+      #[dynamic_object::visitable]
+      mod nodes {
+            use crate::{self as dynamic_object, DynamicObjectBase};
+            use dynamic_object_derive::subclass;
+
+            #[subclass(DynamicObjectBase)]
+            pub struct Node {
+                  pub line: u32
+            }
+
+            #[subclass(Node, node)]
+            pub struct Literal {
+                  pub value: i64,
+                  pub node: Node
+            }
+
+            #[subclass(Node, node)]
+            pub struct BinaryOp {
+                  pub node: Node,
+                  pub op: char
+            }
+
+            #[subclass(Node, node)]
+            pub struct HTTPRequest {
+                  pub node: Node
+            }
+      }
+      use nodes::*;
+
+      #[derive(Default)]
+      struct Printer {
+            output: String
+      }
+
+      impl Visitor for Printer {
+            fn visit_node(&mut self, node: &Node) {
+                  self.output += &std::format!("line {};", node.line);
+            }
+
+            fn visit_literal(&mut self, literal: &Literal) {
+                  self.output += &std::format!("literal {};", literal.value);
+                  self.visit_node(&literal.node);
+            }
+
+            // An acronym is one word
+            fn visit_http_request(&mut self, _: &HTTPRequest) {
+                  self.output += "request;";
+            }
+      }
+
+      struct Renumber(u32);
+
+      impl VisitorMut for Renumber {
+            fn visit_node_mut(&mut self, node: &mut Node) {
+                  node.line = self.0;
+            }
+      }
+
+      #[test]
+      fn accept() {
+            let literal = Object::<Literal>::new(Box::new(Literal { value: 4, node: Node { line: 1 } })).cast::<Node>();
+            let binary = Object::<BinaryOp>::new(Box::new(BinaryOp { node: Node { line: 2 }, op: '+' })).cast::<Node>();
+
+            let mut printer = Printer::default();
+            literal.accept(&mut printer);
+            binary.accept(&mut printer);
+            assert!(printer.output == "literal 4;line 1;line 2;");
+
+            let request = Object::<HTTPRequest>::new(Box::new(HTTPRequest { node: Node { line: 3 } })).cast::<Node>();
+            request.accept(&mut printer);
+            assert!(printer.output.ends_with("line 2;request;"));
+      }
+
+      #[test]
+      fn accept_mut() {
+            let mut binary = Object::<BinaryOp>::new(Box::new(BinaryOp { node: Node { line: 2 }, op: '+' }));
+            binary.accept_mut(&mut Renumber(7));
+            assert!(binary.node.line == 7);
+      }
+}